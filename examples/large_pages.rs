@@ -1,20 +1,22 @@
 use std::io::Write;
 
-use xmmap::{common_huge_page::CommonMmapBuilderHugePage, CommonMmapBuilder, CommonMmapMut, Mmap};
+#[cfg(target_os = "linux")]
+use xmmap::{HugePagePolicy, LinuxMmapBuilderExt};
+use xmmap::{common_huge_page::CommonMmapBuilderHugePage, CommonMmapBuilder, CommonMmapMut, MmapMut};
 
 fn main() -> std::io::Result<()> {
-    let mmap = Mmap::builder()
+    let builder = MmapMut::builder()
         .set_read(true)
-        .set_write(true)
-        .set_len(1024 * 2048 as usize)
-        .set_huge_page(true)
-        .build()?;
-    let mut mutmmap = mmap.as_mut();
-    let slice = mutmmap.as_slice();
+        .set_len(1024 * 2048)
+        .set_huge_page(true);
+    // the hugetlb pool is empty unless configured in /proc/sys/vm/nr_hugepages
+    #[cfg(target_os = "linux")]
+    let builder = builder.set_huge_page_policy(HugePagePolicy::FallbackTransparent);
+    let mut mmap = builder.build_mut()?;
+    println!("mapped with {} byte pages", mmap.page_size());
+    let slice = mmap.as_mut_slice();
     let hello = b"hello from 2MB sized large page~!\n";
-    unsafe {
-        std::ptr::copy_nonoverlapping(hello.as_ptr(), slice.as_mut_ptr(), 34);
-    }
+    slice[..hello.len()].copy_from_slice(hello);
     std::io::stdout().write_all(&slice[0..=34])?;
     Ok(())
 }
@@ -0,0 +1,269 @@
+//! An append only log of records in preallocated, memory mapped segment
+//! files, e.g. for a write ahead log.
+//!
+//! Every record is framed by its length and a CRC-32 of the length and the
+//! payload, both little endian `u32`s. Segments are zero filled when they
+//! are created, so the first record failing its check marks the end of the
+//! log after a crash.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+use crate::{CommonMmapMut, Mmap, MmapBuilder, MmapError, MmapMut, MmapResult, preallocate};
+
+/// length and checksum in front of every record
+const FRAME_LEN: usize = 8;
+const SEGMENT_SUFFIX: &str = ".log";
+
+/// Where a record lives, segments are numbered from zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LogPosition {
+    pub segment: u64,
+    pub offset: usize,
+}
+
+/// An append only log in a directory of segment files.
+///
+/// Only the last segment is mapped, records go to the end of it until the
+/// next one does not fit and a new segment is started. Nothing is durable
+/// before `flush`.
+pub struct MmapLog {
+    dir: PathBuf,
+    segment_len: usize,
+    segment: u64,
+    map: MmapMut,
+    /// end of the valid records in the mapped segment
+    end: usize,
+    /// end of the records flushed to the segment file
+    flushed: usize,
+}
+
+impl MmapLog {
+    /// open the log in `dir`, creating the directory and its first segment
+    /// if needed, and recover the end of the last segment
+    ///
+    /// new segments are preallocated to `segment_len` bytes, existing ones
+    /// are only grown to it
+    pub fn open<P: AsRef<Path>>(dir: P, segment_len: usize) -> MmapResult<MmapLog> {
+        if segment_len <= FRAME_LEN {
+            return Err(MmapError::InvalidConfig(
+                "segments have to be larger than a record frame",
+            ));
+        }
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|err| MmapError::from_io("mkdir", err))?;
+        let segment = segments(&dir)?.last().copied().unwrap_or(0);
+        let file = open_segment(&dir, segment, segment_len)?;
+        let mut map = MmapBuilder::from_file(&file)?.build_mut()?;
+
+        let end = scan(&map, |_, _| {});
+        // a torn write may have left bytes after the last valid record, clear
+        // them so they never pass as records once appends run past them
+        if map[end..].iter().any(|&byte| byte != 0) {
+            map[end..].fill(0);
+            let len = map.len() - end;
+            map.flush_range(end, len)?;
+        }
+        Ok(MmapLog {
+            dir,
+            segment_len,
+            segment,
+            map,
+            end,
+            flushed: end,
+        })
+    }
+
+    /// the largest record that fits into a new segment
+    pub fn max_record_len(&self) -> usize {
+        (self.segment_len - FRAME_LEN).min(u32::MAX as usize)
+    }
+
+    /// where the next record goes if it fits into the current segment
+    pub fn position(&self) -> LogPosition {
+        LogPosition {
+            segment: self.segment,
+            offset: self.end,
+        }
+    }
+
+    /// append `record`, starting a new segment if it does not fit into the
+    /// current one
+    pub fn append(&mut self, record: &[u8]) -> MmapResult<LogPosition> {
+        if record.len() > self.max_record_len() {
+            return Err(MmapError::OutOfRange {
+                start: 0,
+                end: record.len() as u64,
+                len: self.max_record_len() as u64,
+            });
+        }
+        if self.end + FRAME_LEN + record.len() > self.map.len() {
+            self.roll_over()?;
+        }
+        let position = self.position();
+        let len = (record.len() as u32).to_le_bytes();
+        let crc = crc32(crc32_update(!0, &len), record);
+        let start = self.end;
+        self.map[start..start + 4].copy_from_slice(&len);
+        self.map[start + 4..start + FRAME_LEN].copy_from_slice(&crc.to_le_bytes());
+        self.map[start + FRAME_LEN..start + FRAME_LEN + record.len()].copy_from_slice(record);
+        self.end += FRAME_LEN + record.len();
+        Ok(position)
+    }
+
+    /// write the records appended since the last flush to the segment file
+    pub fn flush(&mut self) -> MmapResult<()> {
+        self.map
+            .flush_range(self.flushed, self.end - self.flushed)?;
+        self.flushed = self.end;
+        Ok(())
+    }
+
+    /// call `f` with every record in order, stopping at the end of the valid
+    /// records of each segment
+    pub fn for_each<F: FnMut(LogPosition, &[u8])>(&self, mut f: F) -> MmapResult<()> {
+        for segment in segments(&self.dir)? {
+            if segment == self.segment {
+                scan(&self.map[..self.end], |offset, record| {
+                    f(LogPosition { segment, offset }, record)
+                });
+            } else {
+                let map = Mmap::open(segment_path(&self.dir, segment))?;
+                scan(&map, |offset, record| {
+                    f(LogPosition { segment, offset }, record)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// flush the current segment and map a fresh one after it
+    fn roll_over(&mut self) -> MmapResult<()> {
+        self.flush()?;
+        let segment = self.segment + 1;
+        let file = open_segment(&self.dir, segment, self.segment_len)?;
+        self.map = MmapBuilder::from_file(&file)?.build_mut()?;
+        self.segment = segment;
+        self.end = 0;
+        self.flushed = 0;
+        Ok(())
+    }
+}
+
+fn segment_path(dir: &Path, segment: u64) -> PathBuf {
+    dir.join(format!("{:020}{}", segment, SEGMENT_SUFFIX))
+}
+
+/// the numbers of the segments in `dir`, ascending
+fn segments(dir: &Path) -> MmapResult<Vec<u64>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|err| MmapError::from_io("opendir", err))? {
+        let entry = entry.map_err(|err| MmapError::from_io("readdir", err))?;
+        if let Some(segment) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_suffix(SEGMENT_SUFFIX))
+            .and_then(|number| number.parse().ok())
+        {
+            segments.push(segment);
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+/// open or create a segment, a crash while creating it may have left it
+/// shorter than `len`
+fn open_segment(dir: &Path, segment: u64, len: usize) -> MmapResult<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(segment_path(dir, segment))
+        .map_err(|err| MmapError::from_io("open", err))?;
+    let file_len = file
+        .metadata()
+        .map_err(|err| MmapError::from_io("fstat", err))?
+        .len();
+    if file_len < len as u64 {
+        // allocate the blocks, a sparse segment would fault on appends once
+        // the disk is full
+        preallocate(&file, len as u64)?;
+        file.sync_all()
+            .map_err(|err| MmapError::from_io("fsync", err))?;
+        // the segment is only found after a crash once its entry is durable
+        sync_dir(dir)?;
+    }
+    Ok(file)
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> MmapResult<()> {
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|err| MmapError::from_io("fsync", err))
+}
+
+/// ntfs journals the directory entry with the file, and directories can
+/// not be opened as files anyway
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> MmapResult<()> {
+    Ok(())
+}
+
+/// call `f` with the offset and payload of every valid record at the start
+/// of `bytes`, returns the end of the last one
+fn scan<F: FnMut(usize, &[u8])>(bytes: &[u8], mut f: F) -> usize {
+    let mut offset = 0;
+    while let Some(frame) = bytes.get(offset..offset + FRAME_LEN) {
+        let len_bytes = [frame[0], frame[1], frame[2], frame[3]];
+        let crc = u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]);
+        let len = u32::from_le_bytes(len_bytes) as usize;
+        let record = match bytes.get(offset + FRAME_LEN..offset + FRAME_LEN + len) {
+            Some(record) => record,
+            None => break,
+        };
+        if crc32(crc32_update(!0, &len_bytes), record) != crc {
+            break;
+        }
+        f(offset, record);
+        offset += FRAME_LEN + len;
+    }
+    offset
+}
+
+/// CRC-32 (IEEE) lookup table
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32_update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &byte in bytes {
+        crc = CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// finish a checksum started with `crc32_update(!0, ..)` over `bytes`
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    !crc32_update(crc, bytes)
+}
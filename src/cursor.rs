@@ -0,0 +1,176 @@
+//! `std::io` access to a mapping, for code that wants `Read` or `Write`.
+
+use std::{
+    borrow::BorrowMut,
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
+};
+
+#[cfg(unix)]
+use crate::MmapError;
+use crate::{CommonMmapMut, Mmap, MmapMut};
+
+/// A position in a mapping implementing `Read`, `BufRead` and `Seek`, and
+/// `Write` for writable mappings.
+///
+/// Like `std::io::Cursor` the position may be past the end, reads there
+/// return nothing and writes are cut at the end of the mapping unless auto
+/// grow is enabled. `flush` writes the whole mapping back to its file.
+pub struct MmapCursor<M> {
+    map: M,
+    pos: u64,
+    #[cfg(unix)]
+    grow: bool,
+}
+
+impl<M> MmapCursor<M> {
+    /// a cursor at the start of `map`, `map` may be owned or borrowed
+    pub fn new(map: M) -> MmapCursor<M> {
+        MmapCursor {
+            map,
+            pos: 0,
+            #[cfg(unix)]
+            grow: false,
+        }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    pub fn get_ref(&self) -> &M {
+        &self.map
+    }
+
+    pub fn get_mut(&mut self) -> &mut M {
+        &mut self.map
+    }
+
+    pub fn into_inner(self) -> M {
+        self.map
+    }
+}
+
+#[cfg(unix)]
+impl<M: BorrowMut<MmapMut>> MmapCursor<M> {
+    /// resize the mapping, and the file behind it, to the end of every write
+    /// past its end instead of cutting the write short, writes past the end
+    /// of anonymous mappings fail
+    pub fn set_auto_grow(mut self, grow: bool) -> MmapCursor<M> {
+        self.grow = grow;
+        self
+    }
+
+    pub fn auto_grow(&self) -> bool {
+        self.grow
+    }
+}
+
+impl<M: AsRef<[u8]>> MmapCursor<M> {
+    /// the bytes from the position to the end of the mapping
+    fn remaining(&self) -> &[u8] {
+        let bytes = self.map.as_ref();
+        let start = usize::try_from(self.pos).map_or(bytes.len(), |pos| pos.min(bytes.len()));
+        &bytes[start..]
+    }
+}
+
+impl<M: AsRef<[u8]>> Read for MmapCursor<M> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.remaining();
+        let len = buf.len().min(remaining.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<M: AsRef<[u8]>> BufRead for MmapCursor<M> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.remaining())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt as u64;
+    }
+}
+
+impl<M: AsRef<[u8]>> Seek for MmapCursor<M> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => {
+                self.pos = pos;
+                return Ok(pos);
+            }
+            SeekFrom::End(offset) => (self.map.as_ref().len() as u64, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        match base.checked_add_signed(offset) {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to a negative or overflowing position",
+            )),
+        }
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+impl<M: BorrowMut<MmapMut>> Write for MmapCursor<M> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let map = self.map.borrow_mut();
+        let pos = usize::try_from(self.pos).unwrap_or(usize::MAX);
+        #[cfg(unix)]
+        if self.grow {
+            let end = pos.checked_add(buf.len()).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "write past the address space")
+            })?;
+            if end > map.len() {
+                if map.fd().is_none() {
+                    return Err(MmapError::Unsupported(
+                        "auto grow needs a mapping backed by a file",
+                    )
+                    .into());
+                }
+                map.resize(end)?;
+            }
+        }
+        let start = pos.min(map.len());
+        let len = buf.len().min(map.len() - start);
+        map.as_mut_slice()[start..start + len].copy_from_slice(&buf[..len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(self.map.borrow_mut().flush_all()?)
+    }
+}
+
+impl Mmap {
+    /// a `Read + BufRead + Seek` cursor at the start of the mapping
+    pub fn reader(&self) -> MmapCursor<&Mmap> {
+        MmapCursor::new(self)
+    }
+}
+
+impl MmapMut {
+    /// a `Read + BufRead + Seek` cursor at the start of the mapping
+    pub fn reader(&self) -> MmapCursor<&MmapMut> {
+        MmapCursor::new(self)
+    }
+
+    /// a cursor at the start of the mapping that also implements `Write`
+    pub fn writer(&mut self) -> MmapCursor<&mut MmapMut> {
+        MmapCursor::new(self)
+    }
+}
@@ -0,0 +1,122 @@
+use std::{fmt, io};
+
+pub type MmapResult<T> = Result<T, MmapError>;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MmapError {
+    /// the builder options contradict each other or are incomplete
+    InvalidConfig(&'static str),
+    /// the feature is not available on this platform or for this mapping
+    Unsupported(&'static str),
+    /// `value` has to be a multiple of `alignment`
+    Misaligned {
+        what: &'static str,
+        value: u64,
+        alignment: usize,
+    },
+    /// `[start, end)` does not fit into the `len` bytes of a mapping or file
+    OutOfRange { start: u64, end: u64, len: u64 },
+    /// the process lacks the named privilege, e.g. `SeLockMemoryPrivilege`
+    /// for large pages on windows
+    InsufficientPrivilege(&'static str),
+    /// a guarded access touched a page that can not be read, e.g. because
+    /// the mapped file was truncated, `offset` is the first faulting byte
+    Fault { offset: u64 },
+    /// a system call failed
+    Os { syscall: &'static str, errno: i32 },
+    /// any other io error, e.g. while reading file metadata
+    Io(io::Error),
+}
+
+impl MmapError {
+    /// capture `errno` (or `GetLastError` on windows) after `syscall` failed
+    pub(crate) fn last_os_error(syscall: &'static str) -> MmapError {
+        MmapError::from_io(syscall, io::Error::last_os_error())
+    }
+
+    /// attribute an io error returned by std to `syscall`
+    pub(crate) fn from_io(syscall: &'static str, err: io::Error) -> MmapError {
+        match err.raw_os_error() {
+            Some(errno) => MmapError::Os { syscall, errno },
+            None => MmapError::Io(err),
+        }
+    }
+
+    /// the raw os error code, if any
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            MmapError::Os { errno, .. } => Some(*errno),
+            MmapError::Io(err) => err.raw_os_error(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MmapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MmapError::InvalidConfig(msg) => write!(f, "invalid configuration: {}", msg),
+            MmapError::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+            MmapError::Misaligned {
+                what,
+                value,
+                alignment,
+            } => write!(
+                f,
+                "{} {} is not aligned to {} bytes",
+                what, value, alignment
+            ),
+            MmapError::OutOfRange { start, end, len } => write!(
+                f,
+                "range {}..{} is out of bounds of {} bytes",
+                start, end, len
+            ),
+            MmapError::InsufficientPrivilege(privilege) => {
+                write!(f, "insufficient privilege: {} is not held", privilege)
+            }
+            MmapError::Fault { offset } => {
+                write!(f, "access at offset {} of the mapping faulted", offset)
+            }
+            MmapError::Os { syscall, errno } => write!(
+                f,
+                "{} failed: {}",
+                syscall,
+                io::Error::from_raw_os_error(*errno)
+            ),
+            MmapError::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MmapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MmapError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MmapError {
+    fn from(err: io::Error) -> Self {
+        MmapError::Io(err)
+    }
+}
+
+impl From<MmapError> for io::Error {
+    fn from(err: MmapError) -> Self {
+        let kind = match err {
+            MmapError::InvalidConfig(_) => io::ErrorKind::InvalidInput,
+            MmapError::Unsupported(_) => io::ErrorKind::Unsupported,
+            MmapError::Misaligned { .. } => io::ErrorKind::InvalidInput,
+            MmapError::OutOfRange { .. } => io::ErrorKind::InvalidInput,
+            MmapError::InsufficientPrivilege(_) => io::ErrorKind::PermissionDenied,
+            MmapError::Fault { .. } => io::ErrorKind::UnexpectedEof,
+            // keep `raw_os_error` working for existing callers
+            MmapError::Os { errno, .. } => return io::Error::from_raw_os_error(errno),
+            MmapError::Io(err) => return err,
+        };
+        io::Error::new(kind, err)
+    }
+}
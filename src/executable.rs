@@ -0,0 +1,200 @@
+//! W^X memory for JIT compilers: code is emitted into a writable mapping
+//! which is then flipped to read + execute, so no page is ever writable and
+//! executable at the same time.
+
+use std::{
+    error, fmt,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{
+    CommonMmapBuilder, CommonMmapMut, Mmap, MmapError, MmapMut, MmapResult, Protection,
+    flush_icache,
+};
+
+/// Writable code memory, see `make_executable`.
+pub struct ExecutableMmapMut {
+    map: MmapMut,
+}
+
+/// Read + execute code memory, see `make_mut` for patching.
+pub struct ExecutableMmap {
+    map: Mmap,
+}
+
+impl ExecutableMmapMut {
+    /// an anonymous read + write mapping of `len` bytes to emit code into
+    pub fn new(len: usize) -> MmapResult<ExecutableMmapMut> {
+        // on windows anonymous sections always allow execution, so the view
+        // starts read + write and can be flipped later
+        let map = Mmap::builder().set_len(len).set_read(true).build_mut()?;
+        Ok(ExecutableMmapMut { map })
+    }
+
+    /// drop write access and make the code executable, the instruction cache
+    /// is synchronized with the written bytes
+    ///
+    /// if the protection can not be changed the mapping is handed back
+    /// unchanged with the error
+    pub fn make_executable(self) -> Result<ExecutableMmap, ProtectError<ExecutableMmapMut>> {
+        if let Err(error) = self.map.inner.protect_all(Protection::ReadExecute) {
+            return Err(ProtectError { map: self, error });
+        }
+        let inner = self.map.inner;
+        flush_icache(inner.ptr(), inner.len());
+        Ok(ExecutableMmap {
+            map: Mmap { inner },
+        })
+    }
+}
+
+impl Deref for ExecutableMmapMut {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.map.as_slice()
+    }
+}
+
+impl DerefMut for ExecutableMmapMut {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.map.as_mut_slice()
+    }
+}
+
+impl ExecutableMmap {
+    /// entry point for casting into a function pointer
+    pub fn as_ptr(&self) -> *const u8 {
+        self.map.as_ptr()
+    }
+
+    /// drop execute access and make the code writable again for patching
+    ///
+    /// if the protection can not be changed the mapping is handed back
+    /// unchanged with the error
+    pub fn make_mut(self) -> Result<ExecutableMmapMut, ProtectError<ExecutableMmap>> {
+        if let Err(error) = self.map.inner.protect_all(Protection::ReadWrite) {
+            return Err(ProtectError { map: self, error });
+        }
+        Ok(ExecutableMmapMut {
+            map: MmapMut {
+                inner: self.map.inner,
+            },
+        })
+    }
+}
+
+impl Deref for ExecutableMmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.map.as_slice()
+    }
+}
+
+/// A failed protection change, holding the mapping it was made on so the
+/// code in it is not lost. Converts into the `MmapError` for `?`.
+pub struct ProtectError<T> {
+    map: T,
+    error: MmapError,
+}
+
+impl<T> ProtectError<T> {
+    pub fn error(&self) -> &MmapError {
+        &self.error
+    }
+
+    /// the mapping, with the protection it had before
+    pub fn into_inner(self) -> T {
+        self.map
+    }
+
+    pub fn into_parts(self) -> (T, MmapError) {
+        (self.map, self.error)
+    }
+}
+
+impl<T> fmt::Debug for ProtectError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProtectError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for ProtectError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<T> error::Error for ProtectError<T> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<T> From<ProtectError<T>> for MmapError {
+    fn from(err: ProtectError<T>) -> MmapError {
+        err.error
+    }
+}
+
+/// One memfd mapped twice, a read + write alias to emit and patch code and a
+/// read + execute alias to run it, so code can be patched while it stays
+/// executable without any address being writable and executable.
+#[cfg(target_os = "linux")]
+pub struct DualExecutableMmap {
+    rw: MmapMut,
+    rx: Mmap,
+}
+
+#[cfg(target_os = "linux")]
+impl DualExecutableMmap {
+    /// a memfd of `len` bytes mapped once read + write and once read + execute
+    pub fn new(len: usize) -> MmapResult<DualExecutableMmap> {
+        use crate::{LinuxMmapBuilderExt, RawDescriptor};
+
+        let rw = Mmap::builder()
+            .set_len(len)
+            .set_read(true)
+            .set_memfd("xmmap-jit")
+            .build_mut()?;
+        let rx = Mmap::builder()
+            .set_len(len)
+            .set_discriptor(RawDescriptor::from(
+                &rw.fd().expect("memfd mappings own their descriptor"),
+            ))
+            .set_read(true)
+            .set_execute(true)
+            .build()?;
+        Ok(DualExecutableMmap { rw, rx })
+    }
+
+    /// the writable alias
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.rw.as_mut_slice()
+    }
+
+    /// the executable alias, entry point for casting into a function pointer
+    pub fn as_ptr(&self) -> *const u8 {
+        self.rx.as_ptr()
+    }
+
+    /// make writes to `[offset, offset + len)` of the writable alias visible
+    /// to instruction fetches from the executable alias
+    pub fn flush_icache(&self, offset: usize, len: usize) -> MmapResult<()> {
+        if offset
+            .checked_add(len)
+            .is_none_or(|end| end > self.rx.len())
+        {
+            return Err(crate::MmapError::OutOfRange {
+                start: offset as u64,
+                end: (offset as u64).saturating_add(len as u64),
+                len: self.rx.len() as u64,
+            });
+        }
+        flush_icache(unsafe { self.rx.as_ptr().add(offset) }, len);
+        Ok(())
+    }
+}
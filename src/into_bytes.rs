@@ -0,0 +1,28 @@
+//! Zero copy conversions into `bytes::Bytes`, the mapping is dropped with
+//! the last clone of the `Bytes`.
+//!
+//! There is no conversion into `BytesMut`, which can only own memory from
+//! the global allocator.
+
+use bytes::Bytes;
+
+use crate::{Mmap, MmapMut, MmapView};
+
+impl From<Mmap> for Bytes {
+    fn from(map: Mmap) -> Bytes {
+        Bytes::from_owner(map)
+    }
+}
+
+/// the mapping stays writable but the `Bytes` only hand out shared access
+impl From<MmapMut> for Bytes {
+    fn from(map: MmapMut) -> Bytes {
+        Bytes::from_owner(map)
+    }
+}
+
+impl From<MmapView> for Bytes {
+    fn from(view: MmapView) -> Bytes {
+        Bytes::from_owner(view)
+    }
+}
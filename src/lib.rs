@@ -1,9 +1,34 @@
+mod append_log;
 mod common_builder;
+mod cursor;
+mod error;
+mod executable;
+#[cfg(feature = "bytes")]
+mod into_bytes;
+mod pod;
+mod queue;
+mod region;
+mod vec;
+mod view;
 
-use std::slice;
+use std::{
+    fs::{File, OpenOptions},
+    ops::{Deref, DerefMut, Range},
+    path::Path,
+    slice,
+};
 
 // default export the common builder
+pub use append_log::*;
 pub use common_builder::*;
+pub use cursor::*;
+pub use error::*;
+pub use executable::*;
+pub use pod::*;
+pub use queue::*;
+pub use region::*;
+pub use vec::*;
+pub use view::*;
 
 #[cfg(windows)]
 pub mod windows;
@@ -28,6 +53,10 @@ pub struct MmapBuilder {
     /// https://pubs.opengroup.org/onlinepubs/9699919799/functions/mmap.html
     /// > If `len` is zero, `mmap()` shall fail and no mapping shall be
     /// > established.
+    ///
+    /// so a zero `len` builds an empty mapping without asking the os, unless
+    /// the builder came from `from_file` where it means "up to the end of the
+    /// file"
     pub(crate) len: usize,
     /// no access then it is read only
     pub(crate) read: bool,
@@ -35,34 +64,78 @@ pub struct MmapBuilder {
     pub(crate) execute: bool,
     /// no discriptor means is an anonymous mapping
     pub(crate) descriptor: Option<RawDescriptor>,
+    /// size of the file when the builder was created by `from_file`
+    pub(crate) file_len: Option<u64>,
     // ===== common huge page extra =====
     pub(crate) huge_page: bool,
     // ===== unix common extra =====
     /// the flag is shared but default is false
     /// so I decided to use `private` instead of `shared`
     pub(crate) private: bool,
-    pub(crate) advise_normal: bool,
-    pub(crate) advise_sequential: bool,
-    pub(crate) advise_random: bool,
-    pub(crate) advise_willneed: bool,
-    pub(crate) advise_dontneed: bool,
+    /// applied with `madvise` right after mapping
+    #[cfg(unix)]
+    pub(crate) advice: Option<Advice>,
+    /// address space reserved up front, so resizing never moves the mapping
+    #[cfg(unix)]
+    pub(crate) reserve: usize,
     // ===== unix map stack extra =====
     pub(crate) map_stack: bool,
     // ===== linux extra =====
     pub(crate) map_populate: bool,
     pub(crate) huge_page_1gb: bool,
+    #[cfg(target_os = "linux")]
+    pub(crate) huge_page_policy: HugePagePolicy,
+    /// name of the memfd backing an anonymous mapping
+    #[cfg(target_os = "linux")]
+    pub(crate) memfd: Option<String>,
     // ===== windows extra =====
     /// write and copy_on_write are exclusive
+    #[cfg_attr(not(windows), allow(dead_code))]
     pub(crate) copy_on_write: bool,
 }
 
+/// What the current platform supports, so callers can check up front instead
+/// of handling `MmapError::Unsupported` from `build`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// size of regular pages
+    pub page_size: usize,
+    /// huge page sizes offered by the os, empty if there are none. on linux
+    /// a size being listed does not mean its pool has free pages, see
+    /// `huge_page_sizes`
+    pub huge_page_sizes: Vec<usize>,
+    /// `UnixMmapBuilderExt::set_map_stack`
+    pub map_stack: bool,
+    /// `LinuxMmapBuilderExt::set_populate`
+    pub populate: bool,
+    /// `UnixMmapBuilderExt::set_advice` and `advise`
+    pub advice: bool,
+}
+
+/// query the capabilities of the current platform
+pub fn capabilities() -> Capabilities {
+    platform_capabilities()
+}
+
+/// Access allowed to the pages of a live mapping, see `Mmap::protect`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protection {
+    /// every access faults, e.g. for guard pages
+    None,
+    Read,
+    ReadWrite,
+    ReadExecute,
+    ReadWriteExecute,
+}
+
 pub trait CommonMmapMut {
-    fn flush_all(&self) -> std::io::Result<()>;
-    fn flush_all_non_blocking(&self) -> std::io::Result<()>;
-    fn flush_range(&self, offset: usize, len: usize) -> std::io::Result<()>;
-    fn flush_range_non_blocking(&self, offset: usize, len: usize) -> std::io::Result<()>;
-    fn block_on_flush(&self) -> std::io::Result<()>;
-    fn as_slice(&mut self) -> &mut [u8];
+    fn flush_all(&self) -> MmapResult<()>;
+    fn flush_all_non_blocking(&self) -> MmapResult<()>;
+    fn flush_range(&self, offset: usize, len: usize) -> MmapResult<()>;
+    fn flush_range_non_blocking(&self, offset: usize, len: usize) -> MmapResult<()>;
+    fn block_on_flush(&self) -> MmapResult<()>;
+    fn as_mut_slice(&mut self) -> &mut [u8];
 }
 
 pub mod common_huge_page {
@@ -80,12 +153,281 @@ pub mod common_huge_page {
     }
 }
 
+/// A read only memory map.
+///
+/// The mapping is owned by this value and unmapped when it is dropped, share
+/// it between threads with an `Arc` if needed.
+pub struct Mmap {
+    inner: MmapInner,
+}
+
+/// A writable memory map.
+///
+/// Mutable access is only handed out through `&mut self`, so the borrow
+/// checker guarantees there is never more than one `&mut [u8]` alive and that
+/// no slice outlives the mapping.
+pub struct MmapMut {
+    inner: MmapInner,
+}
+
+impl MmapBuilder {
+    /// a read only builder for `file`, the length defaults to the bytes
+    /// remaining after the offset
+    pub fn from_file(file: &File) -> MmapResult<MmapBuilder> {
+        let file_len = file
+            .metadata()
+            .map_err(|err| MmapError::from_io("fstat", err))?
+            .len();
+        Ok(MmapBuilder {
+            read: true,
+            descriptor: Some(RawDescriptor::from(file)),
+            file_len: Some(file_len),
+            ..Default::default()
+        })
+    }
+
+    /// derive or check the length against the size of the file
+    fn resolve_file_len(&mut self) -> MmapResult<()> {
+        let file_len = match self.file_len {
+            Some(file_len) => file_len,
+            None => return Ok(()),
+        };
+        if self.offset > file_len {
+            return Err(MmapError::OutOfRange {
+                start: self.offset,
+                end: self.offset,
+                len: file_len,
+            });
+        }
+        if self.len == 0 {
+            self.len = usize::try_from(file_len - self.offset)
+                .map_err(|_| MmapError::InvalidConfig("file is too large to be mapped"))?;
+        } else if self
+            .offset
+            .checked_add(self.len as u64)
+            .is_none_or(|end| end > file_len)
+        {
+            return Err(MmapError::OutOfRange {
+                start: self.offset,
+                end: self.offset.saturating_add(self.len as u64),
+                len: file_len,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn build(mut self) -> MmapResult<Mmap> {
+        self.resolve_file_len()?;
+        Ok(Mmap { inner: self.map()? })
+    }
+
+    /// build a writable mapping, write access is implied
+    pub fn build_mut(mut self) -> MmapResult<MmapMut> {
+        self.write = true;
+        self.resolve_file_len()?;
+        Ok(MmapMut { inner: self.map()? })
+    }
+}
+
 impl Mmap {
     pub fn builder() -> MmapBuilder {
         MmapBuilder::default()
     }
 
+    /// map the whole file at `path` read only
+    pub fn open<P: AsRef<Path>>(path: P) -> MmapResult<Mmap> {
+        let file = File::open(path).map_err(|err| MmapError::from_io("open", err))?;
+        MmapBuilder::from_file(&file)?.build()
+    }
+
+    /// map `range` of `file` read only
+    pub fn map_file_range(file: &File, range: Range<u64>) -> MmapResult<Mmap> {
+        MmapBuilder::from_file(file)?
+            .set_offset(range.start)
+            .set_len(file_range_len(&range)?)
+            .build()
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.inner.ptr()
+    }
+
     pub fn as_slice(&self) -> &[u8] {
-        unsafe { slice::from_raw_parts(self.ptr as *mut _, self.len) }
+        unsafe { slice::from_raw_parts(self.inner.ptr(), self.inner.len()) }
+    }
+
+    /// size of the pages actually backing the mapping, which differs from the
+    /// requested huge page size when the build fell back to regular pages
+    pub fn page_size(&self) -> usize {
+        self.inner.page_size()
+    }
+
+    /// change the protection of the pages in `[offset, offset + len)`, the
+    /// start of the range has to be page aligned
+    ///
+    /// # Safety
+    ///
+    /// the range must not be accessed in a way the new protection forbids,
+    /// e.g. through `as_slice` after `Protection::None`, or the process faults
+    pub unsafe fn protect(
+        &self,
+        offset: usize,
+        len: usize,
+        protection: Protection,
+    ) -> MmapResult<()> {
+        self.inner.protect(offset, len, protection)
+    }
+
+    /// make the whole mapping writable, fails if the underlying file was not
+    /// opened for writing
+    pub fn make_mut(self) -> MmapResult<MmapMut> {
+        self.inner.protect_all(Protection::ReadWrite)?;
+        Ok(MmapMut { inner: self.inner })
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsRef<[u8]> for Mmap {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
     }
 }
+
+impl MmapMut {
+    pub fn builder() -> MmapBuilder {
+        MmapBuilder::default()
+    }
+
+    /// map the whole file at `path` writable
+    pub fn open<P: AsRef<Path>>(path: P) -> MmapResult<MmapMut> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|err| MmapError::from_io("open", err))?;
+        MmapBuilder::from_file(&file)?.build_mut()
+    }
+
+    /// map `range` of `file` writable
+    pub fn map_file_range(file: &File, range: Range<u64>) -> MmapResult<MmapMut> {
+        MmapBuilder::from_file(file)?
+            .set_offset(range.start)
+            .set_len(file_range_len(&range)?)
+            .build_mut()
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.inner.ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.inner.ptr()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.inner.ptr(), self.inner.len()) }
+    }
+
+    /// size of the pages actually backing the mapping, which differs from the
+    /// requested huge page size when the build fell back to regular pages
+    pub fn page_size(&self) -> usize {
+        self.inner.page_size()
+    }
+
+    /// change the protection of the pages in `[offset, offset + len)`, the
+    /// start of the range has to be page aligned
+    ///
+    /// # Safety
+    ///
+    /// the range must not be accessed in a way the new protection forbids,
+    /// e.g. writing through `as_mut_slice` after `Protection::Read`, or the
+    /// process faults
+    pub unsafe fn protect(
+        &self,
+        offset: usize,
+        len: usize,
+        protection: Protection,
+    ) -> MmapResult<()> {
+        self.inner.protect(offset, len, protection)
+    }
+
+    /// make the whole mapping read only, e.g. once it is initialized
+    pub fn make_read_only(self) -> MmapResult<Mmap> {
+        self.inner.protect_all(Protection::Read)?;
+        Ok(Mmap { inner: self.inner })
+    }
+}
+
+impl CommonMmapMut for MmapMut {
+    fn flush_all(&self) -> MmapResult<()> {
+        self.flush_range(0, self.inner.len())
+    }
+
+    fn flush_all_non_blocking(&self) -> MmapResult<()> {
+        self.flush_range_non_blocking(0, self.inner.len())
+    }
+
+    fn flush_range(&self, offset: usize, len: usize) -> MmapResult<()> {
+        self.inner.flush(offset, len)
+    }
+
+    fn flush_range_non_blocking(&self, offset: usize, len: usize) -> MmapResult<()> {
+        self.inner.flush_non_blocking(offset, len)
+    }
+
+    fn block_on_flush(&self) -> MmapResult<()> {
+        self.inner.block_on_flush()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.inner.ptr(), self.inner.len()) }
+    }
+}
+
+impl Deref for MmapMut {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl DerefMut for MmapMut {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+impl AsRef<[u8]> for MmapMut {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsMut<[u8]> for MmapMut {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+fn file_range_len(range: &Range<u64>) -> MmapResult<usize> {
+    // an empty range would otherwise mean "up to the end of the file"
+    if range.start >= range.end {
+        return Err(MmapError::InvalidConfig("empty file range"));
+    }
+    usize::try_from(range.end - range.start)
+        .map_err(|_| MmapError::InvalidConfig("file range is too large to be mapped"))
+}
+
+// the mapping is uniquely owned and only accessed through the borrow rules
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+unsafe impl Send for MmapMut {}
+unsafe impl Sync for MmapMut {}
@@ -0,0 +1,208 @@
+//! Typed views into mapped bytes, checked for bounds and alignment.
+
+use std::{mem, ops::Range, slice};
+
+use crate::{Mmap, MmapError, MmapMut, MmapResult};
+
+/// Plain old data: any bit pattern is a valid value and the type has no
+/// padding, so it can be viewed in and written through mapped bytes.
+///
+/// The primitives and arrays of them implement it. Types deriving
+/// `bytemuck::Pod` implement it through [`bytemuck_pod!`] with the
+/// `bytemuck` feature, types deriving zerocopy's `FromBytes`, `IntoBytes`
+/// and `Immutable` through [`zerocopy_pod!`] with the `zerocopy` feature.
+/// There are no blanket implementations, so enabling a feature never
+/// conflicts with an implementation written by hand.
+///
+/// # Safety
+///
+/// the type must not have padding bytes, invalid bit patterns, interior
+/// mutability or pointers
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),*) => {
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+
+impl_pod!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+#[cfg(feature = "bytemuck")]
+#[doc(hidden)]
+pub use bytemuck as __bytemuck;
+
+/// Implement [`Pod`] for types deriving `bytemuck::Pod`, which already rules
+/// out padding and invalid bit patterns.
+///
+/// ```ignore
+/// #[derive(Clone, Copy, Pod, Zeroable)]
+/// #[repr(C)]
+/// struct Entry {
+///     key: u64,
+///     value: u64,
+/// }
+///
+/// xmmap::bytemuck_pod!(Entry);
+/// ```
+#[cfg(feature = "bytemuck")]
+#[macro_export]
+macro_rules! bytemuck_pod {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            const _: () = {
+                fn assert_bytemuck<T: $crate::__bytemuck::Pod>() {}
+                let _ = assert_bytemuck::<$ty>;
+            };
+            unsafe impl $crate::Pod for $ty {}
+        )*
+    };
+}
+
+#[cfg(feature = "zerocopy")]
+#[doc(hidden)]
+pub use zerocopy as __zerocopy;
+
+/// Implement [`Pod`] for types deriving zerocopy's `FromBytes`, `IntoBytes`
+/// and `Immutable`, which already rule out padding and invalid bit patterns.
+///
+/// ```ignore
+/// #[derive(Clone, Copy, FromBytes, IntoBytes, Immutable)]
+/// #[repr(C)]
+/// struct Entry {
+///     key: u64,
+///     value: u64,
+/// }
+///
+/// xmmap::zerocopy_pod!(Entry);
+/// ```
+#[cfg(feature = "zerocopy")]
+#[macro_export]
+macro_rules! zerocopy_pod {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            const _: () = {
+                fn assert_zerocopy<T>()
+                where
+                    T: $crate::__zerocopy::FromBytes
+                        + $crate::__zerocopy::IntoBytes
+                        + $crate::__zerocopy::Immutable
+                        + Copy
+                        + 'static,
+                {
+                }
+                let _ = assert_zerocopy::<$ty>;
+            };
+            unsafe impl $crate::Pod for $ty {}
+        )*
+    };
+}
+
+/// check that `[offset, offset + len)` is inside `bytes` and that it starts
+/// aligned for `T`
+fn check<T: Pod>(bytes: &[u8], offset: usize, len: usize) -> MmapResult<()> {
+    if offset.checked_add(len).is_none_or(|end| end > bytes.len()) {
+        return Err(MmapError::OutOfRange {
+            start: offset as u64,
+            end: (offset as u64).saturating_add(len as u64),
+            len: bytes.len() as u64,
+        });
+    }
+    let addr = bytes.as_ptr() as usize + offset;
+    if !addr.is_multiple_of(mem::align_of::<T>()) {
+        return Err(MmapError::Misaligned {
+            what: "view address",
+            value: addr as u64,
+            alignment: mem::align_of::<T>(),
+        });
+    }
+    Ok(())
+}
+
+/// number of `T` in the byte range `range`
+fn slice_len<T: Pod>(range: &Range<usize>) -> MmapResult<usize> {
+    let size = mem::size_of::<T>();
+    if size == 0 {
+        return Err(MmapError::InvalidConfig(
+            "zero sized types can not be viewed as a slice",
+        ));
+    }
+    let len = range
+        .end
+        .checked_sub(range.start)
+        .ok_or(MmapError::InvalidConfig("view range ends before it starts"))?;
+    if !len.is_multiple_of(size) {
+        return Err(MmapError::Misaligned {
+            what: "view length",
+            value: len as u64,
+            alignment: size,
+        });
+    }
+    Ok(len / size)
+}
+
+pub(crate) fn view<T: Pod>(bytes: &[u8], offset: usize) -> MmapResult<&T> {
+    check::<T>(bytes, offset, mem::size_of::<T>())?;
+    Ok(unsafe { &*(bytes.as_ptr().add(offset) as *const T) })
+}
+
+pub(crate) fn view_mut<T: Pod>(bytes: &mut [u8], offset: usize) -> MmapResult<&mut T> {
+    check::<T>(bytes, offset, mem::size_of::<T>())?;
+    Ok(unsafe { &mut *(bytes.as_mut_ptr().add(offset) as *mut T) })
+}
+
+pub(crate) fn view_slice<T: Pod>(bytes: &[u8], range: Range<usize>) -> MmapResult<&[T]> {
+    let len = slice_len::<T>(&range)?;
+    check::<T>(bytes, range.start, len * mem::size_of::<T>())?;
+    Ok(unsafe { slice::from_raw_parts(bytes.as_ptr().add(range.start) as *const T, len) })
+}
+
+pub(crate) fn view_slice_mut<T: Pod>(
+    bytes: &mut [u8],
+    range: Range<usize>,
+) -> MmapResult<&mut [T]> {
+    let len = slice_len::<T>(&range)?;
+    check::<T>(bytes, range.start, len * mem::size_of::<T>())?;
+    Ok(unsafe { slice::from_raw_parts_mut(bytes.as_mut_ptr().add(range.start) as *mut T, len) })
+}
+
+impl Mmap {
+    /// the `T` at byte `offset`
+    pub fn view<T: Pod>(&self, offset: usize) -> MmapResult<&T> {
+        view(self.as_slice(), offset)
+    }
+
+    /// the `T`s in the byte range `range`, its length has to be a multiple
+    /// of the size of `T`
+    pub fn view_slice<T: Pod>(&self, range: Range<usize>) -> MmapResult<&[T]> {
+        view_slice(self.as_slice(), range)
+    }
+}
+
+impl MmapMut {
+    /// the `T` at byte `offset`
+    pub fn view<T: Pod>(&self, offset: usize) -> MmapResult<&T> {
+        view(self.as_slice(), offset)
+    }
+
+    /// the `T`s in the byte range `range`, its length has to be a multiple
+    /// of the size of `T`
+    pub fn view_slice<T: Pod>(&self, range: Range<usize>) -> MmapResult<&[T]> {
+        view_slice(self.as_slice(), range)
+    }
+
+    /// the `T` at byte `offset`
+    pub fn view_mut<T: Pod>(&mut self, offset: usize) -> MmapResult<&mut T> {
+        view_mut(self, offset)
+    }
+
+    /// the `T`s in the byte range `range`, its length has to be a multiple
+    /// of the size of `T`
+    pub fn view_slice_mut<T: Pod>(&mut self, range: Range<usize>) -> MmapResult<&mut [T]> {
+        view_slice_mut(self, range)
+    }
+}
@@ -0,0 +1,438 @@
+//! A message queue over a shared mapping, so processes mapping the same file
+//! or memfd can exchange variable length messages without copying them
+//! through a pipe.
+//!
+//! The first 4 KiB of the mapping hold the header with the cursors, the rest
+//! is a ring of records. Every record starts with an 8 byte word which is
+//! zero until the record is published and carries its length afterwards.
+//! Records never wrap, a producer pads the end of the ring instead.
+
+use std::{
+    ops::{Deref, DerefMut},
+    slice,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{MmapError, MmapMut, MmapResult};
+
+const MAGIC: u32 = 0x786d_7175;
+const VERSION: u32 = 1;
+/// the header page
+const HEADER_LEN: usize = 4096;
+const WORD: usize = 8;
+/// record word of a published message, the low bits are the message length
+const READY: u64 = 1 << 63;
+/// record word of padding, the low bits are the length of the whole padding
+const PAD: u64 = 1 << 62;
+const LEN_MASK: u64 = PAD - 1;
+
+// header offsets, the cursors live on their own cache lines
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 4;
+const CAPACITY_OFFSET: usize = 8;
+/// bytes ever reserved by producers
+const TAIL_OFFSET: usize = 128;
+/// bytes ever released by the consumer
+const HEAD_OFFSET: usize = 256;
+/// bumped on every publish, the consumer waits on it
+const TAIL_SEQ_OFFSET: usize = 384;
+/// bumped on every release, full producers wait on it
+const HEAD_SEQ_OFFSET: usize = 512;
+const CONSUMER_WAITING_OFFSET: usize = 640;
+const PRODUCERS_WAITING_OFFSET: usize = 768;
+
+/// A queue with any number of producers and a single consumer, across
+/// threads and processes.
+///
+/// Each process builds its own `MmapQueue` from its own shared mapping of
+/// the same object. Producers only need `&self`, receiving needs `&mut self`
+/// and only one process may receive at a time. A producer that dies between
+/// reserving and publishing a record stalls the consumer at that record.
+pub struct MmapQueue {
+    map: MmapMut,
+    capacity: usize,
+}
+
+impl MmapQueue {
+    /// lay out an empty queue over `map`, which has to be shared and start
+    /// on an 8 byte boundary
+    pub fn create(mut map: MmapMut) -> MmapResult<MmapQueue> {
+        let capacity = capacity(&map)?;
+        // a reused file may hold anything
+        map[..HEADER_LEN + capacity].fill(0);
+        let queue = MmapQueue { map, capacity };
+        queue.u32(VERSION_OFFSET).store(VERSION, Ordering::Relaxed);
+        queue
+            .u64(CAPACITY_OFFSET)
+            .store(capacity as u64, Ordering::Relaxed);
+        // the magic goes last, so `open` never sees a half initialized header
+        queue.u32(MAGIC_OFFSET).store(MAGIC, Ordering::Release);
+        Ok(queue)
+    }
+
+    /// attach to a queue created over another mapping of the same object
+    pub fn open(map: MmapMut) -> MmapResult<MmapQueue> {
+        let capacity = capacity(&map)?;
+        let queue = MmapQueue { map, capacity };
+        if queue.u32(MAGIC_OFFSET).load(Ordering::Acquire) != MAGIC {
+            return Err(MmapError::InvalidConfig("mapping does not hold a queue"));
+        }
+        if queue.u32(VERSION_OFFSET).load(Ordering::Relaxed) != VERSION {
+            return Err(MmapError::Unsupported("queue version"));
+        }
+        if queue.u64(CAPACITY_OFFSET).load(Ordering::Relaxed) != capacity as u64 {
+            return Err(MmapError::InvalidConfig(
+                "queue was created over a mapping of another size",
+            ));
+        }
+        Ok(queue)
+    }
+
+    /// size of the ring in bytes
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// the largest message that can be sent, half the ring minus framing so
+    /// a message always fits after padding the end of the ring
+    pub fn max_message_len(&self) -> usize {
+        (self.capacity / 2 / WORD * WORD) - WORD
+    }
+
+    /// reserve room for a message of `len` bytes to be written in place,
+    /// `None` if the queue is full
+    pub fn try_reserve(&self, len: usize) -> MmapResult<Option<Reservation<'_>>> {
+        if len > self.max_message_len() {
+            return Err(MmapError::OutOfRange {
+                start: 0,
+                end: len as u64,
+                len: self.max_message_len() as u64,
+            });
+        }
+        let record = record_len(len);
+        let tail = self.u64(TAIL_OFFSET);
+        loop {
+            // head first, a tail loaded afterwards is never behind it
+            let head = self.u64(HEAD_OFFSET).load(Ordering::Acquire);
+            let current = tail.load(Ordering::Relaxed);
+            let pos = (current % self.capacity as u64) as usize;
+            let pad = if self.capacity - pos < record {
+                self.capacity - pos
+            } else {
+                0
+            };
+            if current + (pad + record) as u64 - head > self.capacity as u64 {
+                return Ok(None);
+            }
+            match tail.compare_exchange_weak(
+                current,
+                current + (pad + record) as u64,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    if pad != 0 {
+                        self.publish(pos, PAD | pad as u64);
+                    }
+                    return Ok(Some(Reservation {
+                        queue: self,
+                        pos: (pos + pad) % self.capacity,
+                        len,
+                        committed: false,
+                    }));
+                }
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// copy `msg` into the queue, `false` if the queue is full
+    pub fn try_send(&self, msg: &[u8]) -> MmapResult<bool> {
+        match self.try_reserve(msg.len())? {
+            Some(mut reservation) => {
+                reservation.copy_from_slice(msg);
+                reservation.commit();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// copy `msg` into the queue, waiting for room if it is full
+    pub fn send(&self, msg: &[u8]) -> MmapResult<()> {
+        self.send_deadline(msg, None).map(|_| ())
+    }
+
+    /// copy `msg` into the queue, waiting up to `timeout` for room, `false`
+    /// if there was none in time
+    pub fn send_timeout(&self, msg: &[u8], timeout: Duration) -> MmapResult<bool> {
+        self.send_deadline(msg, Some(Instant::now() + timeout))
+    }
+
+    fn send_deadline(&self, msg: &[u8], deadline: Option<Instant>) -> MmapResult<bool> {
+        if self.try_send(msg)? {
+            return Ok(true);
+        }
+        let waiting = self.u32(PRODUCERS_WAITING_OFFSET);
+        loop {
+            let seq = self.u32(HEAD_SEQ_OFFSET).load(Ordering::SeqCst);
+            waiting.fetch_add(1, Ordering::SeqCst);
+            // check again now that the consumer knows to wake us
+            let sent = self.try_send(msg);
+            if !matches!(sent, Ok(false)) {
+                waiting.fetch_sub(1, Ordering::SeqCst);
+                return sent;
+            }
+            let timeout = match remaining(deadline) {
+                Some(Duration::ZERO) => {
+                    waiting.fetch_sub(1, Ordering::SeqCst);
+                    return Ok(false);
+                }
+                timeout => timeout,
+            };
+            wait(self.u32(HEAD_SEQ_OFFSET), seq, timeout);
+            waiting.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// the next message, `None` if the queue is empty or the next record is
+    /// still being written
+    pub fn try_recv(&mut self) -> Option<Message<'_>> {
+        let (pos, len) = self.poll()?;
+        Some(Message {
+            queue: self,
+            pos,
+            len,
+        })
+    }
+
+    /// the next message, waiting for one if the queue is empty
+    pub fn recv(&mut self) -> Message<'_> {
+        let (pos, len) = loop {
+            if let Some(record) = self.recv_deadline(None) {
+                break record;
+            }
+        };
+        Message {
+            queue: self,
+            pos,
+            len,
+        }
+    }
+
+    /// the next message, waiting up to `timeout` for one
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<Message<'_>> {
+        let (pos, len) = self.recv_deadline(Some(Instant::now() + timeout))?;
+        Some(Message {
+            queue: self,
+            pos,
+            len,
+        })
+    }
+
+    fn recv_deadline(&self, deadline: Option<Instant>) -> Option<(usize, usize)> {
+        if let Some(record) = self.poll() {
+            return Some(record);
+        }
+        let waiting = self.u32(CONSUMER_WAITING_OFFSET);
+        loop {
+            let seq = self.u32(TAIL_SEQ_OFFSET).load(Ordering::SeqCst);
+            waiting.store(1, Ordering::SeqCst);
+            // check again now that producers know to wake us
+            if let Some(record) = self.poll() {
+                waiting.store(0, Ordering::SeqCst);
+                return Some(record);
+            }
+            let timeout = match remaining(deadline) {
+                Some(Duration::ZERO) => {
+                    waiting.store(0, Ordering::SeqCst);
+                    return None;
+                }
+                timeout => timeout,
+            };
+            wait(self.u32(TAIL_SEQ_OFFSET), seq, timeout);
+            waiting.store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// position and length of the next published message, skipping padding
+    fn poll(&self) -> Option<(usize, usize)> {
+        loop {
+            let head = self.u64(HEAD_OFFSET).load(Ordering::Relaxed);
+            let pos = (head % self.capacity as u64) as usize;
+            let word = self.u64(HEADER_LEN + pos).load(Ordering::Acquire);
+            if word & READY != 0 {
+                return Some((pos, (word & LEN_MASK) as usize));
+            }
+            if word & PAD != 0 {
+                self.release(pos, (word & LEN_MASK) as usize);
+                continue;
+            }
+            return None;
+        }
+    }
+
+    /// store the record word at `pos`, the record is visible to the consumer
+    /// afterwards
+    fn publish(&self, pos: usize, word: u64) {
+        self.u64(HEADER_LEN + pos).store(word, Ordering::Release);
+        self.u32(TAIL_SEQ_OFFSET).fetch_add(1, Ordering::SeqCst);
+        if self.u32(CONSUMER_WAITING_OFFSET).load(Ordering::SeqCst) != 0 {
+            wake(self.u32(TAIL_SEQ_OFFSET));
+        }
+    }
+
+    /// zero the `len` bytes of records at `pos` and hand them back to the
+    /// producers
+    fn release(&self, pos: usize, len: usize) {
+        unsafe {
+            std::ptr::write_bytes(self.data().add(pos), 0, len);
+        }
+        self.u64(HEAD_OFFSET)
+            .fetch_add(len as u64, Ordering::Release);
+        self.u32(HEAD_SEQ_OFFSET).fetch_add(1, Ordering::SeqCst);
+        if self.u32(PRODUCERS_WAITING_OFFSET).load(Ordering::SeqCst) != 0 {
+            wake(self.u32(HEAD_SEQ_OFFSET));
+        }
+    }
+
+    fn data(&self) -> *mut u8 {
+        unsafe { (self.map.as_ptr() as *mut u8).add(HEADER_LEN) }
+    }
+
+    fn u32(&self, offset: usize) -> &AtomicU32 {
+        unsafe { &*(self.map.as_ptr().add(offset) as *const AtomicU32) }
+    }
+
+    fn u64(&self, offset: usize) -> &AtomicU64 {
+        unsafe { &*(self.map.as_ptr().add(offset) as *const AtomicU64) }
+    }
+}
+
+/// Room for one message, published by `commit`. Dropping it without
+/// committing turns the room into padding the consumer skips.
+pub struct Reservation<'a> {
+    queue: &'a MmapQueue,
+    pos: usize,
+    len: usize,
+    committed: bool,
+}
+
+impl Reservation<'_> {
+    /// make the message visible to the consumer
+    pub fn commit(mut self) {
+        self.committed = true;
+        self.queue.publish(self.pos, READY | self.len as u64);
+    }
+}
+
+impl Deref for Reservation<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.queue.data().add(self.pos + WORD), self.len) }
+    }
+}
+
+impl DerefMut for Reservation<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.queue.data().add(self.pos + WORD), self.len) }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.queue
+                .publish(self.pos, PAD | record_len(self.len) as u64);
+        }
+    }
+}
+
+/// A received message, read in place and released on drop.
+pub struct Message<'a> {
+    queue: &'a mut MmapQueue,
+    pos: usize,
+    len: usize,
+}
+
+impl Deref for Message<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.queue.data().add(self.pos + WORD), self.len) }
+    }
+}
+
+impl Drop for Message<'_> {
+    fn drop(&mut self) {
+        self.queue.release(self.pos, record_len(self.len));
+    }
+}
+
+fn capacity(map: &MmapMut) -> MmapResult<usize> {
+    if !(map.as_ptr() as usize).is_multiple_of(WORD) {
+        return Err(MmapError::Misaligned {
+            what: "queue address",
+            value: map.as_ptr() as u64,
+            alignment: WORD,
+        });
+    }
+    let capacity = map.len().saturating_sub(HEADER_LEN) / WORD * WORD;
+    // room for at least one small message
+    if capacity < 4 * WORD {
+        return Err(MmapError::InvalidConfig("mapping is too small for a queue"));
+    }
+    Ok(capacity)
+}
+
+/// the record word followed by the message, padded to the next word
+fn record_len(len: usize) -> usize {
+    WORD + len.next_multiple_of(WORD)
+}
+
+/// time left until `deadline`, `None` for no deadline
+fn remaining(deadline: Option<Instant>) -> Option<Duration> {
+    deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// block while `word` still holds `expected`, the futex is not private as
+/// other processes wake it
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) {
+    let timeout = timeout.map(|timeout| libc::timespec {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    });
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAIT,
+            expected,
+            timeout
+                .as_ref()
+                .map_or(std::ptr::null(), |timeout| timeout as *const libc::timespec),
+        );
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn wake(word: &AtomicU32) {
+    unsafe {
+        libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAKE, i32::MAX);
+    }
+}
+
+/// without futexes waiting is polling
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) {
+    let nap = Duration::from_micros(50);
+    if word.load(Ordering::SeqCst) == expected {
+        std::thread::sleep(timeout.map_or(nap, |timeout| timeout.min(nap)));
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn wake(_word: &AtomicU32) {}
@@ -0,0 +1,107 @@
+//! Reserve a large range of address space up front and back parts of it with
+//! memory on demand, e.g. for arenas that need stable pointers.
+
+use crate::{MmapError, MmapResult, RegionInner};
+
+/// Address space reserved without any memory behind it.
+///
+/// Every page starts inaccessible, `commit` makes page aligned ranges
+/// readable and writable and `decommit` gives their memory back. Only raw
+/// pointers are handed out, so the caller tracks which ranges are committed.
+pub struct ReservedRegion {
+    inner: RegionInner,
+}
+
+impl ReservedRegion {
+    /// reserve `len` bytes of address space, rounded up to whole pages
+    pub fn reserve(len: usize) -> MmapResult<ReservedRegion> {
+        if len == 0 {
+            return Err(MmapError::InvalidConfig("can not reserve zero bytes"));
+        }
+        Ok(ReservedRegion {
+            inner: RegionInner::reserve(len)?,
+        })
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.inner.ptr()
+    }
+
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.inner.ptr()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.len() == 0
+    }
+
+    /// granularity of `commit`, `decommit` and `reset`
+    pub fn page_size(&self) -> usize {
+        self.inner.page_size()
+    }
+
+    /// back `[offset, offset + len)` with zeroed memory on first touch and
+    /// make it readable and writable, committing a committed page again keeps
+    /// its content
+    pub fn commit(&self, offset: usize, len: usize) -> MmapResult<()> {
+        self.check_range(offset, len)?;
+        if len == 0 {
+            return Ok(());
+        }
+        self.inner.commit(offset, len)
+    }
+
+    /// release the memory behind `[offset, offset + len)` and make it
+    /// inaccessible again, it reads as zeros once committed again
+    pub fn decommit(&self, offset: usize, len: usize) -> MmapResult<()> {
+        self.check_range(offset, len)?;
+        if len == 0 {
+            return Ok(());
+        }
+        self.inner.decommit(offset, len)
+    }
+
+    /// let the os reclaim the memory behind `[offset, offset + len)` whenever
+    /// it likes, the range stays committed but its content is undefined until
+    /// it is written again
+    pub fn reset(&self, offset: usize, len: usize) -> MmapResult<()> {
+        self.check_range(offset, len)?;
+        if len == 0 {
+            return Ok(());
+        }
+        self.inner.reset(offset, len)
+    }
+
+    fn check_range(&self, offset: usize, len: usize) -> MmapResult<()> {
+        if offset.checked_add(len).is_none_or(|end| end > self.len()) {
+            return Err(MmapError::OutOfRange {
+                start: offset as u64,
+                end: (offset as u64).saturating_add(len as u64),
+                len: self.len() as u64,
+            });
+        }
+        let page_size = self.page_size();
+        if !offset.is_multiple_of(page_size) {
+            return Err(MmapError::Misaligned {
+                what: "region offset",
+                value: offset as u64,
+                alignment: page_size,
+            });
+        }
+        if !len.is_multiple_of(page_size) {
+            return Err(MmapError::Misaligned {
+                what: "region length",
+                value: len as u64,
+                alignment: page_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+unsafe impl Send for ReservedRegion {}
+unsafe impl Sync for ReservedRegion {}
@@ -0,0 +1,119 @@
+#[cfg(target_os = "linux")]
+use crate::HugePagePolicy;
+use crate::{Advice, MmapBuilder};
+
+pub trait UnixMmapBuilderExt {
+    // setter
+    /// `MAP_PRIVATE` instead of `MAP_SHARED`, writes are copy on write and
+    /// never reach the file
+    fn set_private(self, toggle: bool) -> Self;
+    /// `MAP_STACK`, the mapping is suitable for a thread stack
+    fn set_map_stack(self, toggle: bool) -> Self;
+    /// hint applied with `madvise` once the mapping is created
+    fn set_advice(self, advice: Advice) -> Self;
+    /// reserve `len` bytes of address space for the mapping, so
+    /// `MmapMut::resize` can grow it up to `len` bytes without moving it
+    fn set_reserve(self, len: usize) -> Self;
+    // getter
+    fn private(&self) -> bool;
+    fn map_stack(&self) -> bool;
+    fn advice(&self) -> Option<Advice>;
+    fn reserve(&self) -> usize;
+}
+
+impl UnixMmapBuilderExt for MmapBuilder {
+    fn set_private(mut self, toggle: bool) -> Self {
+        self.private = toggle;
+        self
+    }
+
+    fn set_map_stack(mut self, toggle: bool) -> Self {
+        self.map_stack = toggle;
+        self
+    }
+
+    fn set_advice(mut self, advice: Advice) -> Self {
+        self.advice = Some(advice);
+        self
+    }
+
+    fn set_reserve(mut self, len: usize) -> Self {
+        self.reserve = len;
+        self
+    }
+
+    fn private(&self) -> bool {
+        self.private
+    }
+
+    fn map_stack(&self) -> bool {
+        self.map_stack
+    }
+
+    fn advice(&self) -> Option<Advice> {
+        self.advice
+    }
+
+    fn reserve(&self) -> usize {
+        self.reserve
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub trait LinuxMmapBuilderExt {
+    // setter
+    /// `MAP_POPULATE`, prefault the page tables of the whole mapping
+    fn set_populate(self, toggle: bool) -> Self;
+    /// use 1 GiB instead of 2 MiB pages when huge pages are enabled
+    fn set_huge_page_1gb(self, toggle: bool) -> Self;
+    /// what to do when the requested huge pages are not available
+    fn set_huge_page_policy(self, policy: HugePagePolicy) -> Self;
+    /// back the anonymous mapping with a sealable memfd called `name`
+    /// instead of `MAP_ANON`, so it can be shared with other processes and
+    /// mapped again through its descriptor
+    fn set_memfd(self, name: &str) -> Self;
+    // getter
+    fn populate(&self) -> bool;
+    fn huge_page_1gb(&self) -> bool;
+    fn huge_page_policy(&self) -> HugePagePolicy;
+    fn memfd(&self) -> Option<&str>;
+}
+
+#[cfg(target_os = "linux")]
+impl LinuxMmapBuilderExt for MmapBuilder {
+    fn set_populate(mut self, toggle: bool) -> Self {
+        self.map_populate = toggle;
+        self
+    }
+
+    fn set_huge_page_1gb(mut self, toggle: bool) -> Self {
+        self.huge_page_1gb = toggle;
+        self
+    }
+
+    fn set_huge_page_policy(mut self, policy: HugePagePolicy) -> Self {
+        self.huge_page_policy = policy;
+        self
+    }
+
+    fn set_memfd(mut self, name: &str) -> Self {
+        self.memfd = Some(name.to_owned());
+        self
+    }
+
+    fn populate(&self) -> bool {
+        self.map_populate
+    }
+
+    fn huge_page_1gb(&self) -> bool {
+        self.huge_page_1gb
+    }
+
+    fn huge_page_policy(&self) -> HugePagePolicy {
+        self.huge_page_policy
+    }
+
+    fn memfd(&self) -> Option<&str> {
+        self.memfd.as_deref()
+    }
+}
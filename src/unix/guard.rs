@@ -0,0 +1,274 @@
+//! Guarded copies turning a `SIGBUS` or `SIGSEGV` while reading a mapping
+//! into an error instead of killing the process, e.g. when another process
+//! truncates the mapped file.
+//!
+//! The copy is a small assembly routine. A process wide handler, installed
+//! on first use, checks that the fault hit the bytes the current thread is
+//! copying and moves the saved program counter of the thread past the copy,
+//! which then reports how many bytes are left. Page tables are never
+//! touched, so other threads reading the same pages still fault. Any other
+//! fault goes to the previous handler.
+
+use std::ops::Range;
+
+use super::MmapInner;
+use crate::{Mmap, MmapError, MmapMut, MmapResult};
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod imp {
+    use std::{
+        cell::{Cell, UnsafeCell},
+        mem::{self, MaybeUninit},
+        sync::Once,
+    };
+
+    use crate::MmapResult;
+
+    const SIGNALS: [libc::c_int; 2] = [libc::SIGBUS, libc::SIGSEGV];
+
+    #[derive(Clone, Copy)]
+    struct Guard {
+        /// the addresses being copied
+        start: usize,
+        end: usize,
+        /// where the copy continues after a fault, written by `copy`
+        resume: usize,
+        /// the faulting address
+        fault: usize,
+    }
+
+    impl Guard {
+        const NONE: Guard = Guard {
+            start: 0,
+            end: 0,
+            resume: 0,
+            fault: 0,
+        };
+    }
+
+    thread_local! {
+        static GUARD: Cell<Guard> = const { Cell::new(Guard::NONE) };
+    }
+
+    /// the actions replaced by ours, in the order of `SIGNALS`
+    struct Previous(UnsafeCell<[MaybeUninit<libc::sigaction>; 2]>);
+
+    // written once before our handler is installed, only read afterwards
+    unsafe impl Sync for Previous {}
+
+    static PREVIOUS: Previous = Previous(UnsafeCell::new([MaybeUninit::uninit(); 2]));
+
+    fn install() {
+        static INSTALL: Once = Once::new();
+
+        INSTALL.call_once(|| unsafe {
+            let previous = &mut *PREVIOUS.0.get();
+            for (signum, previous) in SIGNALS.into_iter().zip(previous) {
+                // record the previous action first, so a fault right after
+                // ours is installed can already be passed on
+                libc::sigaction(signum, std::ptr::null(), previous.as_mut_ptr());
+                let mut action: libc::sigaction = mem::zeroed();
+                action.sa_sigaction = handler as *const () as libc::sighandler_t;
+                action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signum, &action, std::ptr::null_mut());
+            }
+        });
+    }
+
+    unsafe extern "C" fn handler(
+        signum: libc::c_int,
+        info: *mut libc::siginfo_t,
+        context: *mut libc::c_void,
+    ) {
+        let addr = (*info).si_addr() as usize;
+        let resume = GUARD
+            .try_with(|cell| {
+                let mut guard = cell.get();
+                if addr < guard.start || addr >= guard.end {
+                    return None;
+                }
+                guard.fault = addr;
+                cell.set(guard);
+                Some(guard.resume)
+            })
+            .ok()
+            .flatten();
+        if let Some(resume) = resume {
+            set_pc(context as *mut libc::ucontext_t, resume);
+            return;
+        }
+
+        let index = SIGNALS.iter().position(|&s| s == signum).unwrap_or(0);
+        let previous = (*PREVIOUS.0.get())[index].assume_init_ref();
+        if previous.sa_flags & libc::SA_SIGINFO != 0 {
+            let previous: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                mem::transmute(previous.sa_sigaction);
+            previous(signum, info, context);
+        } else if previous.sa_sigaction == libc::SIG_DFL || previous.sa_sigaction == libc::SIG_IGN {
+            // returning faults again, now with the default action
+            libc::sigaction(signum, previous, std::ptr::null_mut());
+        } else {
+            let previous: extern "C" fn(libc::c_int) = mem::transmute(previous.sa_sigaction);
+            previous(signum);
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    unsafe fn set_pc(context: *mut libc::ucontext_t, pc: usize) {
+        (*context).uc_mcontext.gregs[libc::REG_RIP as usize] = pc as libc::greg_t;
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    unsafe fn set_pc(context: *mut libc::ucontext_t, pc: usize) {
+        (*context).uc_mcontext.pc = pc as _;
+    }
+
+    /// copy `len` bytes, returns the bytes left when a fault stopped it
+    ///
+    /// `resume` receives the address after the copy before anything is read,
+    /// the handler continues there with the count of the faulting byte
+    #[cfg(target_arch = "x86_64")]
+    unsafe fn raw_copy(dst: *mut u8, src: *const u8, len: usize, resume: *mut usize) -> usize {
+        let remaining;
+        std::arch::asm!(
+            "lea {tmp}, [rip + 2f]",
+            "mov [{resume}], {tmp}",
+            "rep movsb",
+            "2:",
+            resume = in(reg) resume,
+            tmp = out(reg) _,
+            inout("rcx") len => remaining,
+            inout("rdi") dst => _,
+            inout("rsi") src => _,
+            options(nostack, preserves_flags),
+        );
+        remaining
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    unsafe fn raw_copy(dst: *mut u8, src: *const u8, len: usize, resume: *mut usize) -> usize {
+        let remaining;
+        std::arch::asm!(
+            "adr {tmp}, 3f",
+            "str {tmp}, [{resume}]",
+            "cbz {len}, 3f",
+            "2:",
+            "ldrb {byte:w}, [{src}], #1",
+            "strb {byte:w}, [{dst}], #1",
+            "subs {len}, {len}, #1",
+            "b.ne 2b",
+            "3:",
+            resume = in(reg) resume,
+            tmp = out(reg) _,
+            byte = out(reg) _,
+            len = inout(reg) len => remaining,
+            src = inout(reg) src => _,
+            dst = inout(reg) dst => _,
+            options(nostack),
+        );
+        remaining
+    }
+
+    /// copy `src` into `dst`, returns the faulting address if it faulted
+    pub(super) unsafe fn copy(src: *const u8, dst: &mut [u8]) -> MmapResult<Option<usize>> {
+        install();
+        let start = src as usize;
+        let resume = GUARD.with(|cell| {
+            cell.set(Guard {
+                start,
+                end: start + dst.len(),
+                ..Guard::NONE
+            });
+            std::ptr::addr_of_mut!((*cell.as_ptr()).resume)
+        });
+        let remaining = raw_copy(dst.as_mut_ptr(), src, dst.len(), resume);
+        let guard = GUARD.with(|cell| cell.replace(Guard::NONE));
+        Ok((remaining != 0).then_some(guard.fault))
+    }
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+mod imp {
+    use crate::{MmapError, MmapResult};
+
+    pub(super) unsafe fn copy(_src: *const u8, _dst: &mut [u8]) -> MmapResult<Option<usize>> {
+        Err(MmapError::Unsupported(
+            "guarded copies need linux on x86_64 or aarch64",
+        ))
+    }
+}
+
+impl MmapInner {
+    fn try_copy_to(&self, offset: usize, dst: &mut [u8]) -> MmapResult<()> {
+        if offset
+            .checked_add(dst.len())
+            .is_none_or(|end| end > self.len)
+        {
+            return Err(MmapError::OutOfRange {
+                start: offset as u64,
+                end: (offset as u64).saturating_add(dst.len() as u64),
+                len: self.len as u64,
+            });
+        }
+        if dst.is_empty() {
+            return Ok(());
+        }
+        match unsafe { imp::copy(self.ptr().add(offset), dst)? } {
+            None => Ok(()),
+            Some(fault) => Err(MmapError::Fault {
+                offset: (fault - self.ptr() as usize) as u64,
+            }),
+        }
+    }
+
+    fn try_read<R, F: FnOnce(&[u8]) -> R>(&self, range: Range<usize>, f: F) -> MmapResult<R> {
+        if range.start > range.end {
+            return Err(MmapError::OutOfRange {
+                start: range.start as u64,
+                end: range.end as u64,
+                len: self.len as u64,
+            });
+        }
+        let mut bytes = vec![0; range.len()];
+        self.try_copy_to(range.start, &mut bytes)?;
+        Ok(f(&bytes))
+    }
+}
+
+impl Mmap {
+    /// copy `dst.len()` bytes from `offset` into `dst`, a `SIGBUS` or
+    /// `SIGSEGV` while reading the mapping is returned as
+    /// `MmapError::Fault`
+    ///
+    /// only the copying thread is protected, other threads touching the
+    /// faulting pages still get the signal, needs linux on x86_64 or aarch64
+    pub fn try_copy_to(&self, offset: usize, dst: &mut [u8]) -> MmapResult<()> {
+        self.inner.try_copy_to(offset, dst)
+    }
+
+    /// call `f` with a copy of the bytes in `range`, see `try_copy_to`
+    pub fn try_read<R, F: FnOnce(&[u8]) -> R>(&self, range: Range<usize>, f: F) -> MmapResult<R> {
+        self.inner.try_read(range, f)
+    }
+}
+
+impl MmapMut {
+    /// copy `dst.len()` bytes from `offset` into `dst`, see
+    /// `Mmap::try_copy_to`
+    pub fn try_copy_to(&self, offset: usize, dst: &mut [u8]) -> MmapResult<()> {
+        self.inner.try_copy_to(offset, dst)
+    }
+
+    /// call `f` with a copy of the bytes in `range`, see
+    /// `Mmap::try_copy_to`
+    pub fn try_read<R, F: FnOnce(&[u8]) -> R>(&self, range: Range<usize>, f: F) -> MmapResult<R> {
+        self.inner.try_read(range, f)
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    ffi::CString,
+    ops::{BitOr, BitOrAssign},
+    os::unix::prelude::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+};
+
+use crate::{Mmap, MmapError, MmapMut, MmapResult};
+
+pub const HUGE_PAGE_2MB: usize = 2 * 1024 * 1024;
+pub const HUGE_PAGE_1GB: usize = 1024 * 1024 * 1024;
+
+const HUGETLBFS_MAGIC: libc::c_long = 0x958458f6;
+const SYSFS_HUGEPAGES: &str = "/sys/kernel/mm/hugepages";
+
+/// What to do when huge pages of the requested size can not be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HugePagePolicy {
+    /// fail the build
+    #[default]
+    Fail,
+    /// map with regular pages instead
+    FallbackRegular,
+    /// map with regular pages and ask for transparent huge pages with
+    /// `MADV_HUGEPAGE`, whether the kernel backs them with huge pages is
+    /// best effort
+    FallbackTransparent,
+}
+
+/// A huge page size supported by the kernel and its pool counters, as found
+/// in `/sys/kernel/mm/hugepages`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HugePageSize {
+    /// page size in bytes
+    pub size: usize,
+    /// pages in the pool
+    pub total: usize,
+    /// pages in the pool not yet allocated or reserved
+    pub free: usize,
+}
+
+fn read_counter(dir: &std::path::Path, name: &str) -> MmapResult<usize> {
+    std::fs::read_to_string(dir.join(name))?
+        .trim()
+        .parse()
+        .map_err(|_| MmapError::Unsupported("unrecognized hugepages counter in sysfs"))
+}
+
+/// list the huge page sizes supported by the kernel, smallest first
+pub fn huge_page_sizes() -> MmapResult<Vec<HugePageSize>> {
+    let mut sizes = Vec::new();
+    for entry in std::fs::read_dir(SYSFS_HUGEPAGES)? {
+        let entry = entry?;
+        // entries are named like `hugepages-2048kB`
+        let name = entry.file_name();
+        let size = match name
+            .to_str()
+            .and_then(|name| name.strip_prefix("hugepages-"))
+            .and_then(|name| name.strip_suffix("kB"))
+            .and_then(|kb| kb.parse::<usize>().ok())
+        {
+            Some(kb) => kb * 1024,
+            None => continue,
+        };
+        let dir = entry.path();
+        sizes.push(HugePageSize {
+            size,
+            total: read_counter(&dir, "nr_hugepages")?,
+            free: read_counter(&dir, "free_hugepages")?,
+        });
+    }
+    sizes.sort_by_key(|size| size.size);
+    Ok(sizes)
+}
+
+fn is_hugetlbfs(fd: RawFd, size: usize) -> MmapResult<bool> {
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    if unsafe { libc::fstatfs(fd, stat.as_mut_ptr()) } != 0 {
+        return Err(MmapError::last_os_error("fstatfs"));
+    }
+    let stat = unsafe { stat.assume_init() };
+    // the block size of a hugetlbfs mount is its page size
+    Ok(stat.f_type as libc::c_long == HUGETLBFS_MAGIC && stat.f_bsize as usize == size)
+}
+
+/// check whether `len` bytes of `size` huge pages can be mapped, anonymous
+/// mappings need free pages in the pool while files have to live on a
+/// hugetlbfs mount of the same page size
+///
+/// returns the page size to map with or `None` to fall back to regular pages
+pub(crate) fn select_huge_page(
+    size: usize,
+    len: usize,
+    fd: Option<RawFd>,
+    policy: HugePagePolicy,
+) -> MmapResult<Option<usize>> {
+    let available = match fd {
+        Some(fd) => is_hugetlbfs(fd, size)?,
+        None => {
+            let needed = len.div_ceil(size).max(1);
+            huge_page_sizes()
+                .unwrap_or_default()
+                .iter()
+                .any(|pool| pool.size == size && pool.free >= needed)
+        }
+    };
+    match (available, policy) {
+        (true, _) => Ok(Some(size)),
+        (false, HugePagePolicy::Fail) => Err(MmapError::Unsupported(
+            "huge pages of the requested size are not available",
+        )),
+        (false, _policy) => {
+            #[cfg(feature = "log")]
+            log::debug!(
+                "huge pages of {} bytes are not available, falling back with {:?}",
+                size,
+                _policy
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// File seals of a memfd, see `fcntl(2)`, combine them with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Seals(libc::c_int);
+
+impl Seals {
+    /// no new writes or writable mappings, existing mappings keep writing
+    pub const FUTURE_WRITE: Seals = Seals(libc::F_SEAL_FUTURE_WRITE);
+    /// the file can not grow
+    pub const GROW: Seals = Seals(libc::F_SEAL_GROW);
+    /// no more seals can be added
+    pub const SEAL: Seals = Seals(libc::F_SEAL_SEAL);
+    /// the file can not shrink
+    pub const SHRINK: Seals = Seals(libc::F_SEAL_SHRINK);
+    /// the content can not change, adding it fails while any shared mapping
+    /// of the memfd exists, even a read only one, as `mprotect` could make it
+    /// writable
+    pub const WRITE: Seals = Seals(libc::F_SEAL_WRITE);
+
+    pub const fn empty() -> Seals {
+        Seals(0)
+    }
+
+    pub const fn bits(self) -> libc::c_int {
+        self.0
+    }
+
+    pub const fn contains(self, other: Seals) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Seals {
+    type Output = Seals;
+
+    fn bitor(self, rhs: Seals) -> Seals {
+        Seals(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Seals {
+    fn bitor_assign(&mut self, rhs: Seals) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// create a sealable memfd of `len` bytes
+pub(crate) fn memfd_create(name: &str, len: u64) -> MmapResult<OwnedFd> {
+    let name = CString::new(name)
+        .map_err(|_| MmapError::InvalidConfig("memfd name contains a nul byte"))?;
+    let fd =
+        unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING) };
+    if fd < 0 {
+        return Err(MmapError::last_os_error("memfd_create"));
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    if unsafe { libc::ftruncate(fd.as_raw_fd(), len as libc::off_t) } != 0 {
+        return Err(MmapError::last_os_error("ftruncate"));
+    }
+    Ok(fd)
+}
+
+fn add_seals(fd: Option<RawFd>, seals: Seals) -> MmapResult<()> {
+    let fd = fd.ok_or(MmapError::Unsupported(
+        "anonymous mappings have no descriptor to seal",
+    ))?;
+    if unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, seals.0) } == 0 {
+        Ok(())
+    } else {
+        Err(MmapError::last_os_error("fcntl"))
+    }
+}
+
+fn get_seals(fd: Option<RawFd>) -> MmapResult<Seals> {
+    let fd = fd.ok_or(MmapError::Unsupported(
+        "anonymous mappings have no descriptor to seal",
+    ))?;
+    match unsafe { libc::fcntl(fd, libc::F_GET_SEALS) } {
+        -1 => Err(MmapError::last_os_error("fcntl")),
+        seals => Ok(Seals(seals)),
+    }
+}
+
+impl Mmap {
+    /// seal the memfd behind the mapping, see `LinuxMmapBuilderExt::set_memfd`
+    pub fn add_seals(&self, seals: Seals) -> MmapResult<()> {
+        add_seals(self.fd().map(|fd| fd.as_raw_fd()), seals)
+    }
+
+    /// the seals of the memfd behind the mapping
+    pub fn seals(&self) -> MmapResult<Seals> {
+        get_seals(self.fd().map(|fd| fd.as_raw_fd()))
+    }
+}
+
+impl MmapMut {
+    /// seal the memfd behind the mapping, see `LinuxMmapBuilderExt::set_memfd`
+    ///
+    /// `Seals::WRITE` fails as long as this mapping exists, use
+    /// `Seals::FUTURE_WRITE` to keep writing through it
+    pub fn add_seals(&self, seals: Seals) -> MmapResult<()> {
+        add_seals(self.fd().map(|fd| fd.as_raw_fd()), seals)
+    }
+
+    /// the seals of the memfd behind the mapping
+    pub fn seals(&self) -> MmapResult<Seals> {
+        get_seals(self.fd().map(|fd| fd.as_raw_fd()))
+    }
+}
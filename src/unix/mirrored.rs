@@ -0,0 +1,157 @@
+use std::{
+    ops::{Deref, DerefMut},
+    os::unix::prelude::{AsRawFd, OwnedFd},
+    slice,
+};
+
+use super::{mmap_raw, page_size, reserve_flags};
+use crate::{MmapError, MmapResult};
+
+/// A ring buffer whose pages are mapped twice back to back, so a window
+/// wrapping around the end is still one contiguous slice.
+///
+/// Byte `i` and byte `i + len()` are the same memory.
+pub struct MirroredBuffer {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl MirroredBuffer {
+    /// `len` has to be a non zero multiple of the page size
+    pub fn new(len: usize) -> MmapResult<MirroredBuffer> {
+        if len == 0 {
+            return Err(MmapError::InvalidConfig(
+                "a mirrored buffer can not be empty",
+            ));
+        }
+        if !len.is_multiple_of(page_size()) {
+            return Err(MmapError::Misaligned {
+                what: "mirrored buffer length",
+                value: len as u64,
+                alignment: page_size(),
+            });
+        }
+        let double = len
+            .checked_mul(2)
+            .ok_or(MmapError::InvalidConfig("mirrored buffer is too large"))?;
+        let fd = backing(len)?;
+        // reserve both halves first, so nothing else can end up in between
+        let ptr = mmap_raw(
+            std::ptr::null_mut(),
+            double,
+            libc::PROT_NONE,
+            reserve_flags(),
+            -1,
+            0,
+        )?;
+        // from here on the reservation is unmapped on drop
+        let buffer = MirroredBuffer { ptr, len };
+        for half in [ptr, unsafe { ptr.add(len) }] {
+            mmap_raw(
+                half,
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_FIXED,
+                fd.as_raw_fd(),
+                0,
+            )?;
+        }
+        Ok(buffer)
+    }
+
+    /// size of the ring, half of the mapped address space
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr as *const u8
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr as *mut u8
+    }
+
+    /// `len` bytes starting at `head` modulo the ring size
+    pub fn window(&self, head: usize, len: usize) -> MmapResult<&[u8]> {
+        let start = self.window_start(head, len)?;
+        Ok(unsafe { slice::from_raw_parts((self.ptr as *const u8).add(start), len) })
+    }
+
+    /// `len` bytes starting at `head` modulo the ring size
+    pub fn window_mut(&mut self, head: usize, len: usize) -> MmapResult<&mut [u8]> {
+        let start = self.window_start(head, len)?;
+        Ok(unsafe { slice::from_raw_parts_mut((self.ptr as *mut u8).add(start), len) })
+    }
+
+    fn window_start(&self, head: usize, len: usize) -> MmapResult<usize> {
+        let start = head % self.len;
+        if len > self.len {
+            return Err(MmapError::OutOfRange {
+                start: start as u64,
+                end: start as u64 + len as u64,
+                len: self.len as u64,
+            });
+        }
+        Ok(start)
+    }
+}
+
+/// the ring itself, without the mirror
+impl Deref for MirroredBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl DerefMut for MirroredBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr as *mut u8, self.len) }
+    }
+}
+
+impl Drop for MirroredBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, 2 * self.len as libc::size_t);
+        }
+    }
+}
+
+unsafe impl Send for MirroredBuffer {}
+unsafe impl Sync for MirroredBuffer {}
+
+/// an unnamed object of `len` bytes to map twice
+#[cfg(target_os = "linux")]
+fn backing(len: usize) -> MmapResult<OwnedFd> {
+    super::linux::memfd_create("xmmap-mirrored", len as u64)
+}
+
+/// without memfd a shared memory object is created and unlinked right away
+#[cfg(not(target_os = "linux"))]
+fn backing(len: usize) -> MmapResult<OwnedFd> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let name = format!(
+        "/xmmap-mirrored-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    let name = std::ffi::CString::new(name).unwrap_or_default();
+    let fd = super::shm::shm_open(&name, libc::O_RDWR | libc::O_CREAT | libc::O_EXCL, 0o600)?;
+    unsafe {
+        libc::shm_unlink(name.as_ptr());
+    }
+    if unsafe { libc::ftruncate(fd.as_raw_fd(), len as libc::off_t) } != 0 {
+        return Err(MmapError::last_os_error("ftruncate"));
+    }
+    Ok(fd)
+}
@@ -1,9 +1,27 @@
 use std::{
-    os::unix::prelude::{AsRawFd, RawFd},
+    ops::{Deref, DerefMut},
+    os::unix::prelude::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
     sync::atomic::{AtomicUsize, Ordering},
 };
 
-use crate::{MmapBuilder, MmapRawDescriptor};
+use crate::{
+    Capabilities, Mmap, MmapBuilder, MmapError, MmapMut, MmapRawDescriptor, MmapResult, Protection,
+};
+
+mod builder;
+mod guard;
+#[cfg(target_os = "linux")]
+mod linux;
+mod mirrored;
+mod region;
+mod shm;
+
+pub use builder::*;
+#[cfg(target_os = "linux")]
+pub use linux::*;
+pub use mirrored::*;
+pub(crate) use region::*;
+pub use shm::*;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct RawDescriptor(pub RawFd);
@@ -26,40 +44,477 @@ impl AsRef<RawFd> for RawDescriptor {
     }
 }
 
-#[derive(Clone)]
-pub struct Mmap {
-    pub(crate) ptr: *mut libc::c_void,
-    pub(crate) len: usize,
+/// Hints for the kernel about how a range of the mapping is going to be
+/// accessed, see `madvise(2)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// no special treatment, the default
+    Normal,
+    /// pages will be accessed in sequential order, read ahead aggressively
+    Sequential,
+    /// pages will be accessed in random order, disable read ahead
+    Random,
+    /// pages will be accessed soon, start reading them in
+    WillNeed,
+    /// pages will not be accessed soon, the kernel may free them
+    ///
+    /// on a private mapping this discards any modification and the pages read
+    /// back as zeros or as the original file content
+    DontNeed,
+    /// back the range with transparent huge pages where possible
+    #[cfg(target_os = "linux")]
+    HugePage,
+    /// never back the range with transparent huge pages
+    #[cfg(target_os = "linux")]
+    NoHugePage,
 }
 
-impl Mmap {
-    pub fn as_mut(&self) -> MmapMut {
-        MmapMut {
-            ptr: self.ptr,
-            len: self.len,
+impl Advice {
+    fn as_raw(self) -> libc::c_int {
+        match self {
+            Advice::Normal => libc::MADV_NORMAL,
+            Advice::Sequential => libc::MADV_SEQUENTIAL,
+            Advice::Random => libc::MADV_RANDOM,
+            Advice::WillNeed => libc::MADV_WILLNEED,
+            Advice::DontNeed => libc::MADV_DONTNEED,
+            #[cfg(target_os = "linux")]
+            Advice::HugePage => libc::MADV_HUGEPAGE,
+            #[cfg(target_os = "linux")]
+            Advice::NoHugePage => libc::MADV_NOHUGEPAGE,
         }
     }
 }
 
-#[derive(Clone)]
-pub struct MmapMut {
-    pub(crate) ptr: *mut libc::c_void,
-    pub(crate) len: usize,
+pub(crate) struct MmapInner {
+    /// our own duplicate of the descriptor, so it stays valid after the user
+    /// closes theirs
+    fd: Option<OwnedFd>,
+    /// page aligned address and length of the whole os level mapping
+    base: *mut libc::c_void,
+    base_len: usize,
+    /// the window requested by the user, starts `offset % page_size` bytes
+    /// after `base`
+    ptr: *mut libc::c_void,
+    len: usize,
+    /// the page size actually backing the mapping
+    page_size: usize,
+    private: bool,
+    /// address space owned at `base` with `UnixMmapBuilderExt::set_reserve`,
+    /// the pages after `base_len` are `PROT_NONE`
+    reserved: usize,
+    /// `mmap` flags and file offset of `ptr`, to map more pages on resize
+    flags: libc::c_int,
+    offset: u64,
+}
+
+impl MmapInner {
+    pub(crate) fn ptr(&self) -> *mut u8 {
+        self.ptr as *mut u8
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    pub(crate) fn fd(&self) -> Option<BorrowedFd<'_>> {
+        self.fd.as_ref().map(|fd| fd.as_fd())
+    }
+
+    /// validate `[offset, offset + len)` and extend it downwards to the start
+    /// of its first page, as required by `msync` and `madvise`
+    fn page_range(&self, offset: usize, len: usize) -> MmapResult<(*mut libc::c_void, usize)> {
+        if offset.checked_add(len).is_none_or(|end| end > self.len) {
+            return Err(MmapError::OutOfRange {
+                start: offset as u64,
+                end: (offset as u64).saturating_add(len as u64),
+                len: self.len as u64,
+            });
+        }
+        if len == 0 {
+            return Ok((self.ptr, 0));
+        }
+        let addr = self.ptr as usize + offset;
+        let alignment = addr % self.page_size;
+        Ok(((addr - alignment) as *mut libc::c_void, len + alignment))
+    }
+
+    fn madvise(&self, offset: usize, len: usize, advice: Advice) -> MmapResult<()> {
+        let (ptr, len) = self.page_range(offset, len)?;
+        if len == 0 {
+            return Ok(());
+        }
+        if unsafe { libc::madvise(ptr, len as libc::size_t, advice.as_raw()) } == 0 {
+            Ok(())
+        } else {
+            Err(MmapError::last_os_error("madvise"))
+        }
+    }
+
+    /// apply `advice` to a live mapping, pages of a private mapping must not
+    /// be dropped behind the back of outstanding borrows
+    pub(crate) fn advise(&self, offset: usize, len: usize, advice: Advice) -> MmapResult<()> {
+        if advice == Advice::DontNeed && self.private {
+            return Err(MmapError::InvalidConfig(
+                "dontneed would discard the content of a private mapping",
+            ));
+        }
+        self.madvise(offset, len, advice)
+    }
+
+    fn mprotect(
+        &self,
+        ptr: *mut libc::c_void,
+        len: usize,
+        protection: Protection,
+    ) -> MmapResult<()> {
+        let protection = match protection {
+            Protection::None => libc::PROT_NONE,
+            Protection::Read => libc::PROT_READ,
+            Protection::ReadWrite => libc::PROT_READ | libc::PROT_WRITE,
+            Protection::ReadExecute => libc::PROT_READ | libc::PROT_EXEC,
+            Protection::ReadWriteExecute => libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC,
+        };
+        if unsafe { libc::mprotect(ptr, len as libc::size_t, protection) } == 0 {
+            Ok(())
+        } else {
+            Err(MmapError::last_os_error("mprotect"))
+        }
+    }
+
+    /// the caller guarantees the range is not accessed against `protection`
+    pub(crate) fn protect(
+        &self,
+        offset: usize,
+        len: usize,
+        protection: Protection,
+    ) -> MmapResult<()> {
+        let (ptr, aligned_len) = self.page_range(offset, len)?;
+        if aligned_len != len {
+            return Err(MmapError::Misaligned {
+                what: "protect address",
+                value: self.ptr as u64 + offset as u64,
+                alignment: self.page_size,
+            });
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        self.mprotect(ptr, len, protection)
+    }
+
+    pub(crate) fn protect_all(&self, protection: Protection) -> MmapResult<()> {
+        if self.base_len == 0 {
+            return Ok(());
+        }
+        self.mprotect(self.base, self.base_len, protection)
+    }
+
+    fn msync(&self, offset: usize, len: usize, flags: libc::c_int) -> MmapResult<()> {
+        let (ptr, len) = self.page_range(offset, len)?;
+        if len == 0 {
+            return Ok(());
+        }
+        if unsafe { libc::msync(ptr, len as libc::size_t, flags) } == 0 {
+            Ok(())
+        } else {
+            Err(MmapError::last_os_error("msync"))
+        }
+    }
+
+    pub(crate) fn flush(&self, offset: usize, len: usize) -> MmapResult<()> {
+        self.msync(offset, len, libc::MS_SYNC)
+    }
+
+    pub(crate) fn flush_non_blocking(&self, offset: usize, len: usize) -> MmapResult<()> {
+        self.msync(offset, len, libc::MS_ASYNC)
+    }
+
+    pub(crate) fn block_on_flush(&self) -> MmapResult<()> {
+        if let Some(fd) = self.fd.as_ref().map(|fd| fd.as_raw_fd()) {
+            // macos has no `fdatasync`
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            let result = unsafe { libc::fsync(fd) };
+            #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+            let result = unsafe { libc::fdatasync(fd) };
+            if result == 0 {
+                Ok(())
+            } else {
+                Err(MmapError::last_os_error("fdatasync"))
+            }
+        } else {
+            Err(MmapError::Unsupported(
+                "anonymous mappings have no descriptor to sync",
+            ))
+        }
+    }
+
+    /// grow or shrink the window to `new_len` bytes, growing the file first
+    /// if it is too short
+    pub(crate) fn resize(&mut self, new_len: usize) -> MmapResult<()> {
+        if self.page_size != page_size() {
+            return Err(MmapError::Unsupported(
+                "huge page mappings can not be resized",
+            ));
+        }
+        if new_len == self.len {
+            return Ok(());
+        }
+        let alignment = (self.offset % self.page_size as u64) as usize;
+        let aligned_offset = self.offset - alignment as u64;
+        let new_base_len = if new_len == 0 {
+            0
+        } else {
+            (alignment + new_len).next_multiple_of(self.page_size)
+        };
+        // checked before the file grows, a rejected resize leaves it alone
+        if self.reserved != 0 && new_base_len > self.reserved {
+            return Err(MmapError::OutOfRange {
+                start: 0,
+                end: new_len as u64,
+                len: (self.reserved - alignment) as u64,
+            });
+        }
+        if new_len > self.len {
+            if let Some(fd) = &self.fd {
+                grow_file(fd.as_raw_fd(), self.offset + new_len as u64)?;
+            }
+        }
+        let fd = self.fd.as_ref().map_or(-1, |fd| fd.as_raw_fd());
+        // `MmapMut` is always writable, whatever `protect` did to the old pages
+        let protection = libc::PROT_READ | libc::PROT_WRITE;
+
+        if self.reserved != 0 {
+            if new_base_len > self.base_len {
+                mmap_raw(
+                    unsafe { self.base.add(self.base_len) },
+                    new_base_len - self.base_len,
+                    protection,
+                    self.flags | libc::MAP_FIXED,
+                    fd,
+                    aligned_offset + self.base_len as u64,
+                )?;
+            } else if new_base_len < self.base_len {
+                // hand the tail back to the reservation
+                mmap_raw(
+                    unsafe { self.base.add(new_base_len) },
+                    self.base_len - new_base_len,
+                    libc::PROT_NONE,
+                    reserve_flags() | libc::MAP_FIXED,
+                    -1,
+                    0,
+                )?;
+            }
+        } else if self.base_len == 0 {
+            self.base = mmap_raw(
+                std::ptr::null_mut(),
+                new_base_len,
+                protection,
+                self.flags,
+                fd,
+                aligned_offset,
+            )?;
+        } else if new_base_len == 0 {
+            unsafe {
+                libc::munmap(self.base, self.base_len as libc::size_t);
+            }
+            self.base = std::ptr::null_mut();
+        } else {
+            self.base = self.remap(new_base_len, protection, fd, aligned_offset)?;
+        }
+
+        self.base_len = new_base_len;
+        self.ptr = if self.base.is_null() {
+            std::ptr::NonNull::<u8>::dangling().as_ptr() as *mut libc::c_void
+        } else {
+            unsafe { self.base.add(alignment) }
+        };
+        self.len = new_len;
+        Ok(())
+    }
+
+    /// shared anonymous pages live in an object sized when it was mapped,
+    /// `mremap` can not grow it and touching the new pages would fault
+    #[cfg(target_os = "linux")]
+    fn remap(
+        &self,
+        new_base_len: usize,
+        _protection: libc::c_int,
+        _fd: RawFd,
+        _aligned_offset: u64,
+    ) -> MmapResult<*mut libc::c_void> {
+        if new_base_len > self.base_len && self.fd.is_none() && !self.private {
+            return Err(MmapError::Unsupported(
+                "growing shared anonymous mappings needs reserved address space",
+            ));
+        }
+        let base = unsafe {
+            libc::mremap(
+                self.base,
+                self.base_len as libc::size_t,
+                new_base_len as libc::size_t,
+                libc::MREMAP_MAYMOVE,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            Err(MmapError::last_os_error("mremap"))
+        } else {
+            Ok(base)
+        }
+    }
+
+    /// without `mremap` the file is mapped again, which only keeps the
+    /// content of shared file mappings
+    #[cfg(not(target_os = "linux"))]
+    fn remap(
+        &self,
+        new_base_len: usize,
+        protection: libc::c_int,
+        fd: RawFd,
+        aligned_offset: u64,
+    ) -> MmapResult<*mut libc::c_void> {
+        if new_base_len < self.base_len {
+            let tail = unsafe { self.base.add(new_base_len) };
+            if unsafe { libc::munmap(tail, (self.base_len - new_base_len) as libc::size_t) } != 0 {
+                return Err(MmapError::last_os_error("munmap"));
+            }
+            return Ok(self.base);
+        }
+        if self.fd.is_none() || self.private {
+            return Err(MmapError::Unsupported(
+                "growing anonymous or private mappings needs reserved address space on this \
+                 platform",
+            ));
+        }
+        let base = mmap_raw(
+            std::ptr::null_mut(),
+            new_base_len,
+            protection,
+            self.flags,
+            fd,
+            aligned_offset,
+        )?;
+        unsafe {
+            libc::munmap(self.base, self.base_len as libc::size_t);
+        }
+        Ok(base)
+    }
+}
+
+impl Mmap {
+    /// the descriptor backing the mapping, `None` for anonymous mappings,
+    /// see `FdMmap` for `AsFd`
+    pub fn fd(&self) -> Option<BorrowedFd<'_>> {
+        self.inner.fd()
+    }
+
+    /// advise the kernel how `[offset, offset + len)` is going to be accessed
+    pub fn advise(&self, offset: usize, len: usize, advice: Advice) -> MmapResult<()> {
+        self.inner.advise(offset, len, advice)
+    }
 }
 
 impl MmapMut {
-    pub fn as_slice(&mut self) -> &mut [u8] {
-        unsafe { std::slice::from_raw_parts_mut(self.ptr as *mut u8, self.len) }
+    /// the descriptor backing the mapping, `None` for anonymous mappings,
+    /// see `FdMmap` for `AsFd`
+    pub fn fd(&self) -> Option<BorrowedFd<'_>> {
+        self.inner.fd()
+    }
+
+    /// grow or shrink the mapping to `new_len` bytes and return the new view,
+    /// a file that is too short is extended but never shrunk
+    ///
+    /// the mapping may move unless enough address space was reserved with
+    /// `UnixMmapBuilderExt::set_reserve`, pages added by growing are read +
+    /// write. Growing a shared anonymous mapping needs such a reservation,
+    /// on linux only private anonymous mappings grow without one
+    pub fn resize(&mut self, new_len: usize) -> MmapResult<&mut [u8]> {
+        self.inner.resize(new_len)?;
+        Ok(&mut self[..])
+    }
+
+    /// advise the kernel how `[offset, offset + len)` is going to be accessed
+    pub fn advise(&self, offset: usize, len: usize, advice: Advice) -> MmapResult<()> {
+        self.inner.advise(offset, len, advice)
+    }
+}
+
+/// A mapping backed by a descriptor, a file, a memfd or a shared memory
+/// object, so unlike anonymous mappings it implements `AsFd` and `AsRawFd`.
+///
+/// Converting an anonymous mapping, or failing to duplicate the descriptor,
+/// hands the mapping back. The wrapper keeps its own duplicate, so `as_fd`
+/// returns the descriptor it was converted with even if the mapping is
+/// swapped out through `DerefMut`.
+pub struct FdMmap<M> {
+    map: M,
+    fd: OwnedFd,
+}
+
+impl<M> FdMmap<M> {
+    pub fn into_inner(self) -> M {
+        self.map
+    }
+}
+
+impl<M> Deref for FdMmap<M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.map
+    }
+}
+
+impl<M> DerefMut for FdMmap<M> {
+    fn deref_mut(&mut self) -> &mut M {
+        &mut self.map
     }
 }
 
-impl Drop for Mmap {
+impl<M> AsFd for FdMmap<M> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl<M> AsRawFd for FdMmap<M> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+macro_rules! impl_fd_mmap {
+    ($($map:ty),*) => {
+        $(
+            impl TryFrom<$map> for FdMmap<$map> {
+                type Error = $map;
+
+                fn try_from(map: $map) -> Result<FdMmap<$map>, $map> {
+                    match map.fd().map(|fd| fd.try_clone_to_owned()) {
+                        Some(Ok(fd)) => Ok(FdMmap { map, fd }),
+                        _ => Err(map),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_fd_mmap!(Mmap, MmapMut);
+
+impl Drop for MmapInner {
     fn drop(&mut self) {
         // Any errors during unmapping/closing are ignored as the only way
         // to report them would be through panicking which is highly discouraged
         // in Drop impls, c.f. https://github.com/rust-lang/lang-team/issues/97
-        unsafe {
-            libc::munmap(self.ptr, self.len as _);
+        let len = self.base_len.max(self.reserved);
+        if len != 0 {
+            unsafe {
+                libc::munmap(self.base, len as _);
+            }
         }
     }
 }
@@ -79,9 +534,167 @@ fn page_size() -> usize {
     }
 }
 
+/// flags of the `PROT_NONE` pages reserving address space
+fn reserve_flags() -> libc::c_int {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return libc::MAP_PRIVATE | libc::MAP_ANON | libc::MAP_NORESERVE;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    return libc::MAP_PRIVATE | libc::MAP_ANON;
+}
+
+fn mmap_raw(
+    addr: *mut libc::c_void,
+    len: usize,
+    protection: libc::c_int,
+    flags: libc::c_int,
+    fd: RawFd,
+    offset: u64,
+) -> MmapResult<*mut libc::c_void> {
+    // anonymous mappings want a zero offset on some platforms
+    let offset = if fd < 0 { 0 } else { offset };
+    let ptr = unsafe {
+        libc::mmap(
+            addr,
+            len as libc::size_t,
+            protection,
+            flags,
+            fd,
+            offset as libc::off_t,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        Err(MmapError::last_os_error("mmap"))
+    } else {
+        Ok(ptr)
+    }
+}
+
+/// make `file` at least `len` bytes long with its blocks allocated where the
+/// filesystem allows, see `grow_file`
+pub(crate) fn preallocate(file: &std::fs::File, len: u64) -> MmapResult<()> {
+    grow_file(file.as_raw_fd(), len)
+}
+
+/// make the file at least `len` bytes long, on linux the blocks are allocated
+/// so running out of space fails here instead of faulting on access
+fn grow_file(fd: RawFd, len: u64) -> MmapResult<()> {
+    let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+    if unsafe { libc::fstat(fd, stat.as_mut_ptr()) } != 0 {
+        return Err(MmapError::last_os_error("fstat"));
+    }
+    let size = unsafe { stat.assume_init() }.st_size as u64;
+    if size >= len {
+        return Ok(());
+    }
+    #[cfg(target_os = "linux")]
+    {
+        if unsafe { libc::fallocate(fd, 0, size as libc::off_t, (len - size) as libc::off_t) } == 0
+        {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EOPNOTSUPP) {
+            return Err(MmapError::from_io("fallocate", err));
+        }
+    }
+    if unsafe { libc::ftruncate(fd, len as libc::off_t) } == 0 {
+        Ok(())
+    } else {
+        Err(MmapError::last_os_error("ftruncate"))
+    }
+}
+
+/// make freshly written code visible to instruction fetches, aarch64 does
+/// not keep the instruction cache coherent with data writes
+#[cfg(target_arch = "aarch64")]
+pub(crate) fn flush_icache(ptr: *const u8, len: usize) {
+    use std::arch::asm;
+
+    if len == 0 {
+        return;
+    }
+    unsafe {
+        // cache line sizes are log2 of words in CTR_EL0
+        let ctr: u64;
+        asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack));
+        let dline = 4usize << ((ctr >> 16) & 0xf);
+        let iline = 4usize << (ctr & 0xf);
+        let start = ptr as usize;
+        let end = start + len;
+
+        let mut addr = start & !(dline - 1);
+        while addr < end {
+            asm!("dc cvau, {}", in(reg) addr, options(nostack));
+            addr += dline;
+        }
+        asm!("dsb ish", options(nostack));
+        let mut addr = start & !(iline - 1);
+        while addr < end {
+            asm!("ic ivau, {}", in(reg) addr, options(nostack));
+            addr += iline;
+        }
+        asm!("dsb ish", "isb", options(nostack));
+    }
+}
+
+/// the instruction cache is coherent on every other supported architecture
+#[cfg(not(target_arch = "aarch64"))]
+pub(crate) fn flush_icache(_ptr: *const u8, _len: usize) {}
+
+#[cfg(target_os = "macos")]
+const SUPERPAGE_2MB: usize = 2 * 1024 * 1024;
+
+/// superpages are only offered by some machines, e.g. not on apple silicon,
+/// so try to map one
+#[cfg(target_os = "macos")]
+fn superpages_available() -> bool {
+    match mmap_raw(
+        std::ptr::null_mut(),
+        SUPERPAGE_2MB,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_PRIVATE | libc::MAP_ANON,
+        libc::VM_FLAGS_SUPERPAGE_SIZE_2MB,
+        0,
+    ) {
+        Ok(ptr) => {
+            unsafe {
+                libc::munmap(ptr, SUPERPAGE_2MB);
+            }
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+pub(crate) fn platform_capabilities() -> Capabilities {
+    #[cfg(target_os = "linux")]
+    let huge_page_sizes = huge_page_sizes()
+        .map(|sizes| sizes.iter().map(|size| size.size).collect())
+        .unwrap_or_default();
+    #[cfg(target_os = "macos")]
+    let huge_page_sizes = if superpages_available() {
+        vec![SUPERPAGE_2MB]
+    } else {
+        Vec::new()
+    };
+    #[cfg(not(target_os = "macos"))]
+    #[cfg(not(target_os = "linux"))]
+    let huge_page_sizes = Vec::new();
+    Capabilities {
+        page_size: page_size(),
+        huge_page_sizes,
+        map_stack: cfg!(any(
+            all(target_os = "linux", not(target_arch = "mips")),
+            target_os = "freebsd",
+            target_os = "android"
+        )),
+        populate: cfg!(target_os = "linux"),
+        advice: true,
+    }
+}
+
 impl MmapBuilder {
-    pub fn build(self) -> std::io::Result<Mmap> {
-        // TODO: large page + offset
+    pub(crate) fn map(self) -> MmapResult<MmapInner> {
         // private
         let flags = if self.private {
             libc::MAP_PRIVATE
@@ -106,9 +719,8 @@ impl MmapBuilder {
                 let protection = libc::PROT_READ;
                 Ok(protection)
             }
-            _ => Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "invalid access",
+            _ => Err(MmapError::InvalidConfig(
+                "read access is required and can only be combined with write and execute",
             )),
         }?;
         let mut flags = flags;
@@ -119,10 +731,40 @@ impl MmapBuilder {
                 flags |= libc::MAP_POPULATE;
             }
         }
-        let (flags, raw_desc) = if let Some(fd) = self.descriptor {
-            (flags, fd.0)
-        } else {
-            (flags | libc::MAP_ANON, -1)
+        // a memfd replaces `MAP_ANON` and is owned by the mapping
+        #[cfg(target_os = "linux")]
+        let memfd = match &self.memfd {
+            Some(_) if self.descriptor.is_some() => {
+                return Err(MmapError::InvalidConfig(
+                    "a memfd can not be combined with a descriptor",
+                ));
+            }
+            Some(name) => {
+                let len = self
+                    .offset
+                    .checked_add(self.len as u64)
+                    .ok_or(MmapError::InvalidConfig("memfd offset and length overflow"))?;
+                Some(linux::memfd_create(name, len)?)
+            }
+            None => None,
+        };
+        #[cfg(not(target_os = "linux"))]
+        let memfd: Option<OwnedFd> = None;
+        let fd = match &memfd {
+            Some(memfd) => Some(memfd.as_raw_fd()),
+            None => self.descriptor.map(|fd| fd.0),
+        };
+        if fd.is_none() {
+            flags |= libc::MAP_ANON;
+        }
+        // duplicate the descriptor up front, so a failure leaves nothing to
+        // clean up
+        let owned_fd = match memfd {
+            Some(memfd) => Some(memfd),
+            None => fd
+                .map(|fd| unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned())
+                .transpose()
+                .map_err(|err| MmapError::from_io("fcntl", err))?,
         };
         // map_stack
         let flags = if self.map_stack {
@@ -140,119 +782,226 @@ impl MmapBuilder {
                 target_os = "android"
             )))]
             {
-                println!("map_stack is not supported on this platform");
-                flags
+                return Err(MmapError::Unsupported(
+                    "MAP_STACK is not supported on this platform",
+                ));
             }
         } else {
             flags
         };
-        // huge page
-        let flags = if self.huge_page {
-            #[cfg(target_os = "linux")]
-            {
-                if self.huge_page_1gb {
-                    flags | libc::MAP_HUGE_1GB
-                } else {
-                    flags | libc::MAP_HUGE_2MB
-                }
+        if self.reserve != 0 {
+            if self.reserve < self.len {
+                return Err(MmapError::InvalidConfig(
+                    "reservation is smaller than the mapping",
+                ));
             }
-            #[cfg(target_os = "macos")]
-            {
-                if self.huge_page_1gb {
-                    flags | libc::SUPERPAGE_SIZE_ANY
-                } else {
-                    flags | libc::SUPERPAGE_SIZE_2MB
-                }
+            if self.huge_page {
+                return Err(MmapError::Unsupported(
+                    "huge pages can not be combined with a reservation",
+                ));
             }
-            #[cfg(not(target_os = "macos"))]
-            #[cfg(not(target_os = "linux"))]
-            {
-                unimplemented!()
+        }
+        // `libc::mmap` does not support zero-size mappings. POSIX defines:
+        //
+        // https://pubs.opengroup.org/onlinepubs/9699919799/functions/mmap.html
+        // > If `len` is zero, `mmap()` shall fail and no mapping shall be established.
+        //
+        // So if we would create such a mapping, hand out an empty one instead
+        if self.len == 0 && self.reserve == 0 {
+            return Ok(MmapInner {
+                fd: owned_fd,
+                base: std::ptr::null_mut(),
+                base_len: 0,
+                ptr: std::ptr::NonNull::<u8>::dangling().as_ptr() as *mut libc::c_void,
+                len: 0,
+                page_size: page_size(),
+                private: self.private,
+                reserved: 0,
+                flags,
+                offset: self.offset,
+            });
+        }
+        // the mapping is placed at the start of the reservation
+        let reservation = if self.reserve != 0 {
+            let alignment = (self.offset % page_size() as u64) as usize;
+            let len = (alignment + self.reserve).next_multiple_of(page_size());
+            let base = mmap_raw(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_NONE,
+                reserve_flags(),
+                -1,
+                0,
+            )?;
+            if self.len == 0 {
+                return Ok(MmapInner {
+                    fd: owned_fd,
+                    base,
+                    base_len: 0,
+                    ptr: unsafe { base.add(alignment) },
+                    len: 0,
+                    page_size: page_size(),
+                    private: self.private,
+                    reserved: len,
+                    flags,
+                    offset: self.offset,
+                });
             }
+            Some((base, len))
         } else {
-            flags
+            None
+        };
+
+        // huge page
+        #[cfg(target_os = "linux")]
+        let huge_page = if self.huge_page {
+            let size = if self.huge_page_1gb {
+                linux::HUGE_PAGE_1GB
+            } else {
+                linux::HUGE_PAGE_2MB
+            };
+            linux::select_huge_page(size, self.len, fd, self.huge_page_policy)?
+        } else {
+            None
+        };
+        // superpages are requested through the descriptor argument, so only
+        // anonymous mappings can have them
+        #[cfg(target_os = "macos")]
+        let huge_page = if self.huge_page {
+            if self.huge_page_1gb {
+                return Err(MmapError::Unsupported("macos only offers 2MB superpages"));
+            }
+            if fd.is_some() {
+                return Err(MmapError::Unsupported(
+                    "superpages are only available for anonymous mappings on macos",
+                ));
+            }
+            Some(SUPERPAGE_2MB)
+        } else {
+            None
         };
-        // advise
-        let mut advise = 0;
-        if self.advise_dontneed {
-            advise |= libc::MADV_DONTNEED;
-        }
-        if self.advise_willneed {
-            advise |= libc::MADV_WILLNEED;
-        }
-        if self.advise_dontneed && self.advise_willneed {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "both dontneed and willneed are not supported",
+        #[cfg(not(target_os = "macos"))]
+        #[cfg(not(target_os = "linux"))]
+        let huge_page: Option<usize> = if self.huge_page {
+            return Err(MmapError::Unsupported(
+                "huge pages are not supported on this platform",
             ));
-        }
-        if self.advise_normal {
-            advise |= libc::MADV_NORMAL;
-        }
-        if self.advise_sequential {
-            advise |= libc::MADV_SEQUENTIAL;
-        }
-        if self.advise_random {
-            advise |= libc::MADV_RANDOM;
-        }
-        if (self.advise_normal && self.advise_sequential)
-            || (self.advise_normal && self.advise_random)
-            || (self.advise_sequential && self.advise_random)
+        } else {
+            None
+        };
+
+        let addr = reservation.map_or(std::ptr::null_mut(), |(base, _)| base);
+        let result = self.mmap_aligned(addr, protection, flags, fd, huge_page);
+        // the pool may still run dry between checking and mapping
+        #[cfg(target_os = "linux")]
+        let (result, huge_page) = match result {
+            Err(_err) if huge_page.is_some() && self.huge_page_policy != HugePagePolicy::Fail => {
+                #[cfg(feature = "log")]
+                log::debug!(
+                    "hugetlb mapping failed, falling back to regular pages: {}",
+                    _err
+                );
+                (self.mmap_aligned(addr, protection, flags, fd, None), None)
+            }
+            result => (result, huge_page),
+        };
+        let (base, base_len, alignment) = match result {
+            Ok(result) => result,
+            Err(err) => {
+                if let Some((base, len)) = reservation {
+                    unsafe {
+                        libc::munmap(base, len as libc::size_t);
+                    }
+                }
+                return Err(err);
+            }
+        };
+        // from here on the mapping is unmapped on drop if advising fails
+        let inner = MmapInner {
+            fd: owned_fd,
+            base,
+            base_len,
+            ptr: unsafe { base.add(alignment) },
+            len: self.len,
+            page_size: huge_page.unwrap_or_else(page_size),
+            private: self.private,
+            reserved: reservation.map_or(0, |(_, len)| len),
+            flags,
+            offset: self.offset,
+        };
+        #[cfg(target_os = "linux")]
+        if self.huge_page
+            && huge_page.is_none()
+            && self.huge_page_policy == HugePagePolicy::FallbackTransparent
         {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "only one of normal, sequential, and random is supported",
-            ));
+            // best effort, kernels without transparent huge pages say EINVAL
+            if let Err(_err) = inner.madvise(0, inner.len, Advice::HugePage) {
+                #[cfg(feature = "log")]
+                log::debug!("MADV_HUGEPAGE failed, keeping regular pages: {}", _err);
+            }
         }
-        let alignment = if self.huge_page {
+        if let Some(advice) = self.advice {
+            inner.madvise(0, inner.len, advice)?;
+        }
+        Ok(inner)
+    }
+
+    /// map the pages covering `[offset, offset + len)`, using `huge_page`
+    /// sized pages if given and replacing the reservation at `addr` if it is
+    /// not null
+    ///
+    /// returns the base address, the mapped length and the distance from the
+    /// base to `offset`
+    fn mmap_aligned(
+        &self,
+        addr: *mut libc::c_void,
+        protection: libc::c_int,
+        flags: libc::c_int,
+        fd: Option<RawFd>,
+        huge_page: Option<usize>,
+    ) -> MmapResult<(*mut libc::c_void, usize, usize)> {
+        let page = huge_page.unwrap_or_else(page_size);
+        let alignment = (self.offset % page as u64) as usize;
+        let aligned_offset = self.offset - alignment as u64;
+        let aligned_len = self.len + alignment;
+        let (aligned_len, flags) = match huge_page {
+            // hugetlb mappings have to cover whole huge pages, munmap fails
+            // otherwise
             #[cfg(target_os = "linux")]
-            {
-                self.offset
-                    % if self.huge_page_1gb {
-                        1024 * 1024 * 1024
-                    } else {
-                        2 * 1024 * 1024
-                    } as u64
+            Some(size) => {
+                let flags = if fd.is_none() {
+                    flags
+                        | libc::MAP_HUGETLB
+                        | ((size.trailing_zeros() as libc::c_int) << libc::MAP_HUGE_SHIFT)
+                } else {
+                    flags
+                };
+                (aligned_len.div_ceil(size) * size, flags)
             }
             #[cfg(target_os = "macos")]
-            {
-                self.offset % 2 * 1024 * 1024 as u64
-            }
-            #[cfg(not(target_os = "macos"))]
-            #[cfg(not(target_os = "linux"))]
-            {
-                unimplemented!()
-            }
+            Some(size) => (aligned_len.div_ceil(size) * size, flags),
+            _ => (aligned_len.next_multiple_of(page), flags),
+        };
+        // macos takes the superpage size in place of the descriptor
+        #[cfg(target_os = "macos")]
+        let (fd, aligned_offset) = match huge_page {
+            Some(_) => (Some(libc::VM_FLAGS_SUPERPAGE_SIZE_2MB), 0),
+            None => (fd, aligned_offset),
+        };
+        let flags = if addr.is_null() {
+            flags
         } else {
-            self.offset % page_size() as u64
+            flags | libc::MAP_FIXED
         };
-        let aligned_offset = self.offset - alignment;
-        let aligned_len = self.len + alignment as usize;
-        // `libc::mmap` does not support zero-size mappings. POSIX defines:
-        //
-        // https://pubs.opengroup.org/onlinepubs/9699919799/functions/mmap.html
-        // > If `len` is zero, `mmap()` shall fail and no mapping shall be established.
-        //
-        // So if we would create such a mapping, crate a one-byte mapping instead:
-        let aligned_len = aligned_len.max(1);
-        unsafe {
-            let ptr = libc::mmap(
-                std::ptr::null_mut(),
-                aligned_len as libc::size_t,
-                protection,
-                flags,
-                raw_desc,
-                aligned_offset as libc::off_t,
-            );
-            if ptr == libc::MAP_FAILED {
-                Err(std::io::Error::last_os_error())
-            } else {
-                Ok(Mmap {
-                    ptr,
-                    len: aligned_len,
-                })
-            }
-        }
+        let ptr = mmap_raw(
+            addr,
+            aligned_len,
+            protection,
+            flags,
+            fd.unwrap_or(-1),
+            aligned_offset,
+        )?;
+        Ok((ptr, aligned_len, alignment))
     }
 }
 
@@ -0,0 +1,101 @@
+use super::{page_size, reserve_flags};
+use crate::{MmapError, MmapResult};
+
+pub(crate) struct RegionInner {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl RegionInner {
+    pub(crate) fn reserve(len: usize) -> MmapResult<RegionInner> {
+        let len = len.next_multiple_of(page_size());
+        let ptr = super::mmap_raw(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_NONE,
+            reserve_flags(),
+            -1,
+            0,
+        )?;
+        Ok(RegionInner { ptr, len })
+    }
+
+    pub(crate) fn ptr(&self) -> *mut u8 {
+        self.ptr as *mut u8
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn page_size(&self) -> usize {
+        page_size()
+    }
+
+    fn mprotect(&self, offset: usize, len: usize, protection: libc::c_int) -> MmapResult<()> {
+        if unsafe { libc::mprotect(self.ptr.add(offset), len as libc::size_t, protection) } == 0 {
+            Ok(())
+        } else {
+            Err(MmapError::last_os_error("mprotect"))
+        }
+    }
+
+    fn madvise(&self, offset: usize, len: usize, advice: libc::c_int) -> MmapResult<()> {
+        if unsafe { libc::madvise(self.ptr.add(offset), len as libc::size_t, advice) } == 0 {
+            Ok(())
+        } else {
+            Err(MmapError::last_os_error("madvise"))
+        }
+    }
+
+    pub(crate) fn commit(&self, offset: usize, len: usize) -> MmapResult<()> {
+        self.mprotect(offset, len, libc::PROT_READ | libc::PROT_WRITE)
+    }
+
+    pub(crate) fn decommit(&self, offset: usize, len: usize) -> MmapResult<()> {
+        // private anonymous pages are dropped right away and read as zeros
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            self.madvise(offset, len, libc::MADV_DONTNEED)?;
+            self.mprotect(offset, len, libc::PROT_NONE)
+        }
+        // elsewhere `MADV_DONTNEED` may keep the pages, so map fresh ones over
+        // the range instead
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        {
+            super::mmap_raw(
+                unsafe { self.ptr.add(offset) },
+                len,
+                libc::PROT_NONE,
+                reserve_flags() | libc::MAP_FIXED,
+                -1,
+                0,
+            )?;
+            Ok(())
+        }
+    }
+
+    pub(crate) fn reset(&self, offset: usize, len: usize) -> MmapResult<()> {
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd"
+        ))]
+        match self.madvise(offset, len, libc::MADV_FREE) {
+            // `MADV_FREE` is only known to linux 4.5 and later
+            Err(MmapError::Os { errno, .. }) if errno == libc::EINVAL => {}
+            result => return result,
+        }
+        self.madvise(offset, len, libc::MADV_DONTNEED)
+    }
+}
+
+impl Drop for RegionInner {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len as libc::size_t);
+        }
+    }
+}
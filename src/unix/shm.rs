@@ -0,0 +1,145 @@
+use std::{
+    ffi::CString,
+    fs::File,
+    ops::{Deref, DerefMut},
+    os::unix::prelude::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+};
+
+use crate::{MmapBuilder, MmapError, MmapMut, MmapResult};
+
+/// A named POSIX shared memory region, see `shm_open(3)`.
+///
+/// The region is mapped writable as a whole. The name is removed when the
+/// value that created it is dropped, processes which still have it mapped
+/// keep their mapping.
+pub struct SharedMemory {
+    map: MmapMut,
+    name: CString,
+    owner: bool,
+}
+
+impl SharedMemory {
+    /// create the region `name` with `len` bytes and the permission bits
+    /// `mode`, fails if it already exists
+    pub fn create(name: &str, len: usize, mode: u32) -> MmapResult<SharedMemory> {
+        let name = shm_name(name)?;
+        let fd = shm_open(&name, libc::O_RDWR | libc::O_CREAT | libc::O_EXCL, mode)?;
+        // remove the name again if sizing or mapping fails
+        let map = size_and_map(fd, len).inspect_err(|_| unsafe {
+            libc::shm_unlink(name.as_ptr());
+        })?;
+        Ok(SharedMemory {
+            map,
+            name,
+            owner: true,
+        })
+    }
+
+    /// open the existing region `name` and map all of it
+    pub fn open(name: &str) -> MmapResult<SharedMemory> {
+        let name = shm_name(name)?;
+        let fd = shm_open(&name, libc::O_RDWR, 0)?;
+        let map = MmapBuilder::from_file(&File::from(fd))?.build_mut()?;
+        Ok(SharedMemory {
+            map,
+            name,
+            owner: false,
+        })
+    }
+
+    /// open the region `name` or create it like `create` if it does not exist
+    ///
+    /// a region that was just created by another process may still be empty
+    /// until its creator sized it
+    pub fn open_or_create(name: &str, len: usize, mode: u32) -> MmapResult<SharedMemory> {
+        match SharedMemory::create(name, len, mode) {
+            Err(MmapError::Os { errno, .. }) if errno == libc::EEXIST => SharedMemory::open(name),
+            result => result,
+        }
+    }
+
+    /// the name including its leading slash
+    pub fn name(&self) -> &str {
+        // built from a `&str`
+        self.name.to_str().unwrap_or_default()
+    }
+
+    /// whether the name is removed on drop
+    pub fn is_owner(&self) -> bool {
+        self.owner
+    }
+
+    /// keep the name around after drop, or take over removing it
+    pub fn set_owner(&mut self, owner: bool) {
+        self.owner = owner;
+    }
+}
+
+impl Deref for SharedMemory {
+    type Target = MmapMut;
+
+    fn deref(&self) -> &MmapMut {
+        &self.map
+    }
+}
+
+impl DerefMut for SharedMemory {
+    fn deref_mut(&mut self) -> &mut MmapMut {
+        &mut self.map
+    }
+}
+
+impl AsFd for SharedMemory {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.map
+            .fd()
+            .expect("shared memory is mapped from its descriptor")
+    }
+}
+
+impl AsRawFd for SharedMemory {
+    fn as_raw_fd(&self) -> RawFd {
+        self.as_fd().as_raw_fd()
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        if self.owner {
+            unsafe {
+                libc::shm_unlink(self.name.as_ptr());
+            }
+        }
+    }
+}
+
+/// portable names start with a single slash
+fn shm_name(name: &str) -> MmapResult<CString> {
+    let name = if name.starts_with('/') {
+        name.to_owned()
+    } else {
+        format!("/{}", name)
+    };
+    CString::new(name)
+        .map_err(|_| MmapError::InvalidConfig("shared memory name contains a nul byte"))
+}
+
+pub(super) fn shm_open(name: &CString, flags: libc::c_int, mode: u32) -> MmapResult<OwnedFd> {
+    // `shm_open` is variadic on apple platforms
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    let fd = unsafe { libc::shm_open(name.as_ptr(), flags, mode as libc::c_uint) };
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    let fd = unsafe { libc::shm_open(name.as_ptr(), flags, mode as libc::mode_t) };
+    if fd < 0 {
+        Err(MmapError::last_os_error("shm_open"))
+    } else {
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
+
+fn size_and_map(fd: OwnedFd, len: usize) -> MmapResult<MmapMut> {
+    if unsafe { libc::ftruncate(fd.as_raw_fd(), len as libc::off_t) } != 0 {
+        return Err(MmapError::last_os_error("ftruncate"));
+    }
+    MmapBuilder::from_file(&File::from(fd))?.build_mut()
+}
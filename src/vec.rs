@@ -0,0 +1,319 @@
+//! A vector of plain old data stored in a file and accessed through a
+//! mapping, so it survives restarts without (de)serialization.
+
+use std::{
+    fs::{File, OpenOptions},
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut},
+    path::Path,
+    slice,
+};
+
+#[cfg(windows)]
+use crate::CommonMmapBuilder;
+use crate::{CommonMmapMut, MmapBuilder, MmapError, MmapMut, MmapResult, Pod, preallocate};
+
+const MAGIC: u64 = u64::from_le_bytes(*b"xmmapvec");
+const VERSION: u32 = 1;
+/// the header is followed by the elements, its length bounds their alignment
+const HEADER_LEN: usize = 64;
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 8;
+const ELEM_SIZE_OFFSET: usize = 12;
+const LEN_OFFSET: usize = 16;
+/// the smallest data area, so tiny vectors do not remap on every push
+const MIN_DATA_LEN: usize = 4096 - HEADER_LEN;
+
+/// A growable array of `T` persisted in a file.
+///
+/// The file starts with a header holding the length, the element size and a
+/// format version, the elements follow. Growing doubles the capacity and
+/// may move the mapping, so pointers into the vector do not survive a push.
+/// The length in the header is only as durable as the last `flush` or
+/// `sync`.
+pub struct MmapVec<T: Pod> {
+    file: File,
+    map: MmapMut,
+    len: usize,
+    /// the file could not be mapped again after shrinking it, `map` is an
+    /// empty stand in and `len` is zero
+    #[cfg(windows)]
+    lost: bool,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> MmapVec<T> {
+    /// open the vector stored at `path`, creating an empty one if the file
+    /// does not exist or is empty
+    pub fn open<P: AsRef<Path>>(path: P) -> MmapResult<MmapVec<T>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|err| MmapError::from_io("open", err))?;
+        MmapVec::from_file(file)
+    }
+
+    /// like `open` for a file opened for reading and writing
+    pub fn from_file(file: File) -> MmapResult<MmapVec<T>> {
+        if mem::size_of::<T>() == 0 {
+            return Err(MmapError::InvalidConfig(
+                "zero sized types can not be stored",
+            ));
+        }
+        if mem::align_of::<T>() > HEADER_LEN {
+            return Err(MmapError::Unsupported(
+                "elements are aligned to at most 64 bytes",
+            ));
+        }
+        let file_len = file
+            .metadata()
+            .map_err(|err| MmapError::from_io("fstat", err))?
+            .len();
+        if file_len == 0 {
+            preallocate(&file, (HEADER_LEN + MIN_DATA_LEN) as u64)?;
+            let mut vec = MmapVec {
+                map: map_file(&file)?,
+                file,
+                len: 0,
+                #[cfg(windows)]
+                lost: false,
+                _marker: PhantomData,
+            };
+            vec.write_header()?;
+            return Ok(vec);
+        }
+
+        let map = map_file(&file)?;
+        if map.len() < HEADER_LEN || *map.view::<u64>(MAGIC_OFFSET)? != MAGIC {
+            return Err(MmapError::InvalidConfig("file does not hold a vector"));
+        }
+        if *map.view::<u32>(VERSION_OFFSET)? != VERSION {
+            return Err(MmapError::Unsupported("vector version"));
+        }
+        if *map.view::<u32>(ELEM_SIZE_OFFSET)? as usize != mem::size_of::<T>() {
+            return Err(MmapError::InvalidConfig(
+                "vector was stored with another element size",
+            ));
+        }
+        let len = *map.view::<u64>(LEN_OFFSET)?;
+        let capacity = ((map.len() - HEADER_LEN) / mem::size_of::<T>()) as u64;
+        if len > capacity {
+            return Err(MmapError::OutOfRange {
+                start: 0,
+                end: len,
+                len: capacity,
+            });
+        }
+        Ok(MmapVec {
+            file,
+            map,
+            len: len as usize,
+            #[cfg(windows)]
+            lost: false,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// elements that fit before the file has to grow
+    pub fn capacity(&self) -> usize {
+        (self.map.len() - HEADER_LEN) / mem::size_of::<T>()
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.data() as *const T, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.data() as *mut T, self.len) }
+    }
+
+    pub fn push(&mut self, value: T) -> MmapResult<()> {
+        self.extend_from_slice(slice::from_ref(&value))
+    }
+
+    pub fn extend_from_slice(&mut self, values: &[T]) -> MmapResult<()> {
+        self.reserve(values.len())?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                values.as_ptr(),
+                (self.data() as *mut T).add(self.len),
+                values.len(),
+            );
+        }
+        self.set_len(self.len + values.len());
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let value = *self.as_slice().last()?;
+        self.set_len(self.len - 1);
+        Some(value)
+    }
+
+    /// drop the elements from `len` on, the file keeps its size
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.set_len(len);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// grow the file so `additional` more elements fit, at least doubling
+    /// the capacity
+    pub fn reserve(&mut self, additional: usize) -> MmapResult<()> {
+        self.check_mapped()?;
+        let needed = self
+            .len
+            .checked_add(additional)
+            .ok_or(MmapError::InvalidConfig("vector capacity overflow"))?;
+        if needed <= self.capacity() {
+            return Ok(());
+        }
+        let capacity = needed.max(self.capacity() * 2);
+        self.grow_to(capacity)
+    }
+
+    /// shrink the file to the elements in use
+    ///
+    /// on windows the file is unmapped while it is truncated, if it can not
+    /// be mapped again the vector is left empty and every fallible method
+    /// fails, reopen it to get at the elements again
+    pub fn shrink_to_fit(&mut self) -> MmapResult<()> {
+        self.check_mapped()?;
+        let data_len = (self.len * mem::size_of::<T>()).max(MIN_DATA_LEN);
+        if HEADER_LEN + data_len >= self.map.len() {
+            return Ok(());
+        }
+        // unmap the tail before it disappears from the file
+        #[cfg(unix)]
+        {
+            self.map.resize(HEADER_LEN + data_len)?;
+            set_file_len(&self.file, HEADER_LEN + data_len)
+        }
+        // windows refuses to truncate a file with a mapped view, so the
+        // whole view goes and the file is mapped again afterwards
+        #[cfg(windows)]
+        {
+            // a header sized stand in leaves no room for elements
+            self.map = MmapMut::builder()
+                .set_read(true)
+                .set_len(HEADER_LEN)
+                .build_mut()?;
+            let truncated = set_file_len(&self.file, HEADER_LEN + data_len);
+            match map_file(&self.file) {
+                Ok(map) => self.map = map,
+                Err(err) => {
+                    self.len = 0;
+                    self.lost = true;
+                    return Err(err);
+                }
+            }
+            truncated
+        }
+    }
+
+    /// write the modified pages back to the file
+    pub fn flush(&self) -> MmapResult<()> {
+        self.check_mapped()?;
+        self.map.flush_all()
+    }
+
+    /// `flush` and wait until the data and the file size are on disk
+    pub fn sync(&self) -> MmapResult<()> {
+        self.check_mapped()?;
+        self.map.flush_all()?;
+        self.file
+            .sync_all()
+            .map_err(|err| MmapError::from_io("fsync", err))
+    }
+
+    fn grow_to(&mut self, capacity: usize) -> MmapResult<()> {
+        let file_len = capacity
+            .checked_mul(mem::size_of::<T>())
+            .and_then(|len| len.checked_add(HEADER_LEN))
+            .ok_or(MmapError::InvalidConfig("vector capacity overflow"))?;
+        // `resize` allocates the new blocks of the file
+        #[cfg(unix)]
+        self.map.resize(file_len)?;
+        #[cfg(windows)]
+        {
+            preallocate(&self.file, file_len as u64)?;
+            self.map = map_file(&self.file)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    fn check_mapped(&self) -> MmapResult<()> {
+        if self.lost {
+            return Err(MmapError::InvalidConfig(
+                "vector lost its mapping while shrinking, reopen it",
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(windows))]
+    fn check_mapped(&self) -> MmapResult<()> {
+        Ok(())
+    }
+
+    fn set_len(&mut self, len: usize) {
+        self.len = len;
+        // the header is always mapped and aligned
+        if let Ok(header) = self.map.view_mut::<u64>(LEN_OFFSET) {
+            *header = len as u64;
+        }
+    }
+
+    fn write_header(&mut self) -> MmapResult<()> {
+        *self.map.view_mut::<u64>(MAGIC_OFFSET)? = MAGIC;
+        *self.map.view_mut::<u32>(VERSION_OFFSET)? = VERSION;
+        *self.map.view_mut::<u32>(ELEM_SIZE_OFFSET)? = mem::size_of::<T>() as u32;
+        *self.map.view_mut::<u64>(LEN_OFFSET)? = self.len as u64;
+        Ok(())
+    }
+
+    fn data(&self) -> *mut u8 {
+        unsafe { (self.map.as_ptr() as *mut u8).add(HEADER_LEN) }
+    }
+}
+
+impl<T: Pod> Deref for MmapVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T: Pod> DerefMut for MmapVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+/// only used to shrink, growing goes through `preallocate`
+fn set_file_len(file: &File, len: usize) -> MmapResult<()> {
+    file.set_len(len as u64)
+        .map_err(|err| MmapError::from_io("ftruncate", err))
+}
+
+fn map_file(file: &File) -> MmapResult<MmapMut> {
+    MmapBuilder::from_file(file)?.build_mut()
+}
@@ -0,0 +1,258 @@
+//! Owned handles to sub-ranges of one mapping, which is unmapped when the
+//! last handle is dropped.
+
+use std::{
+    num::NonZeroUsize,
+    ops::{Deref, DerefMut, Range},
+    slice,
+    sync::Arc,
+};
+
+#[cfg(unix)]
+use crate::Advice;
+use crate::{Mmap, MmapError, MmapMut, MmapResult};
+
+/// A read only range of a shared mapping, cheap to clone and to split.
+#[derive(Clone)]
+pub struct MmapView {
+    map: Arc<Mmap>,
+    offset: usize,
+    len: usize,
+}
+
+/// A writable range of a shared mapping.
+///
+/// Splitting hands out disjoint ranges, so each one can be written from a
+/// different thread. A split that fails leaves the view as it was.
+pub struct MmapViewMut {
+    map: Arc<Mmap>,
+    offset: usize,
+    len: usize,
+}
+
+/// check that `range` fits into a view of `len` bytes
+fn check_range(range: &Range<usize>, len: usize) -> MmapResult<()> {
+    if range.start > range.end || range.end > len {
+        return Err(MmapError::OutOfRange {
+            start: range.start as u64,
+            end: range.end as u64,
+            len: len as u64,
+        });
+    }
+    Ok(())
+}
+
+impl MmapView {
+    /// offset of the view in the mapping
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        unsafe { self.map.inner.ptr().add(self.offset) }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+
+    /// a view of `range` of this view
+    pub fn slice(&self, range: Range<usize>) -> MmapResult<MmapView> {
+        check_range(&range, self.len)?;
+        Ok(MmapView {
+            map: self.map.clone(),
+            offset: self.offset + range.start,
+            len: range.len(),
+        })
+    }
+
+    /// views of `[0, mid)` and `[mid, len)`
+    pub fn split_at(&self, mid: usize) -> MmapResult<(MmapView, MmapView)> {
+        Ok((self.slice(0..mid)?, self.slice(mid..self.len)?))
+    }
+
+    /// views of `chunk_len` bytes each, the last one may be shorter
+    pub fn chunks(&self, chunk_len: NonZeroUsize) -> impl Iterator<Item = MmapView> {
+        let chunk_len = chunk_len.get();
+        let view = self.clone();
+        (0..self.len).step_by(chunk_len).map(move |start| MmapView {
+            map: view.map.clone(),
+            offset: view.offset + start,
+            len: chunk_len.min(view.len - start),
+        })
+    }
+
+    /// advise the kernel how the view is going to be accessed
+    #[cfg(unix)]
+    pub fn advise(&self, advice: Advice) -> MmapResult<()> {
+        self.map.inner.advise(self.offset, self.len, advice)
+    }
+}
+
+impl MmapViewMut {
+    /// offset of the view in the mapping
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        unsafe { self.map.inner.ptr().add(self.offset) }
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        unsafe { self.map.inner.ptr().add(self.offset) }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+    }
+
+    /// keep `[0, at)` in this view and return a view of `[at, len)`
+    pub fn split_off(&mut self, at: usize) -> MmapResult<MmapViewMut> {
+        check_range(&(0..at), self.len)?;
+        let tail = MmapViewMut {
+            map: self.map.clone(),
+            offset: self.offset + at,
+            len: self.len - at,
+        };
+        self.len = at;
+        Ok(tail)
+    }
+
+    /// views of `[0, mid)` and `[mid, len)`, like `MmapView::split_at` but
+    /// consuming the view, which is handed back with the error if `mid` is
+    /// out of range
+    pub fn split_at(
+        mut self,
+        mid: usize,
+    ) -> Result<(MmapViewMut, MmapViewMut), (MmapViewMut, MmapError)> {
+        match self.split_off(mid) {
+            Ok(tail) => Ok((self, tail)),
+            Err(err) => Err((self, err)),
+        }
+    }
+
+    /// views of `chunk_len` bytes each, the last one may be shorter
+    pub fn chunks(self, chunk_len: NonZeroUsize) -> impl Iterator<Item = MmapViewMut> {
+        let chunk_len = chunk_len.get();
+        (0..self.len)
+            .step_by(chunk_len)
+            .map(move |start| MmapViewMut {
+                map: self.map.clone(),
+                offset: self.offset + start,
+                len: chunk_len.min(self.len - start),
+            })
+    }
+
+    /// give up write access, e.g. to share the view between threads
+    pub fn into_view(self) -> MmapView {
+        MmapView {
+            map: self.map,
+            offset: self.offset,
+            len: self.len,
+        }
+    }
+
+    /// write the view back to the file and wait for it
+    pub fn flush(&self) -> MmapResult<()> {
+        self.map.inner.flush(self.offset, self.len)
+    }
+
+    /// start writing the view back to the file without waiting for it
+    pub fn flush_non_blocking(&self) -> MmapResult<()> {
+        self.map.inner.flush_non_blocking(self.offset, self.len)
+    }
+
+    /// advise the kernel how the view is going to be accessed
+    #[cfg(unix)]
+    pub fn advise(&self, advice: Advice) -> MmapResult<()> {
+        self.map.inner.advise(self.offset, self.len, advice)
+    }
+}
+
+impl Deref for MmapView {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsRef<[u8]> for MmapView {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl Deref for MmapViewMut {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl DerefMut for MmapViewMut {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+impl AsRef<[u8]> for MmapViewMut {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsMut<[u8]> for MmapViewMut {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+impl Mmap {
+    /// a view of the whole mapping, which is unmapped when the last view
+    /// derived from it is dropped
+    pub fn into_view(self) -> MmapView {
+        let len = self.len();
+        MmapView {
+            map: Arc::new(self),
+            offset: 0,
+            len,
+        }
+    }
+}
+
+impl MmapMut {
+    /// a writable view of the whole mapping, which is unmapped when the last
+    /// view derived from it is dropped
+    pub fn into_view_mut(self) -> MmapViewMut {
+        let len = self.len();
+        // the pages stay writable, only the views hand out mutable access
+        MmapViewMut {
+            map: Arc::new(Mmap { inner: self.inner }),
+            offset: 0,
+            len,
+        }
+    }
+}
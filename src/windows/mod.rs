@@ -1,9 +1,6 @@
 #![allow(clippy::zst_offset)]
 
-use std::{
-    os::windows::prelude::{AsRawHandle, RawHandle},
-    slice,
-};
+use std::os::windows::prelude::{AsRawHandle, RawHandle};
 
 use widestring::U16CString;
 use winapi::{
@@ -17,19 +14,25 @@ use winapi::{
             UnmapViewOfFile, VirtualProtect, FILE_MAP_ALL_ACCESS, FILE_MAP_COPY, FILE_MAP_EXECUTE,
             FILE_MAP_LARGE_PAGES, FILE_MAP_READ,
         },
-        processthreadsapi::{GetCurrentProcess, OpenProcessToken},
+        processthreadsapi::{FlushInstructionCache, GetCurrentProcess, OpenProcessToken},
         securitybaseapi::AdjustTokenPrivileges,
         sysinfoapi::GetSystemInfo,
         winbase::LookupPrivilegeValueW,
         winnt::{
-            DUPLICATE_SAME_ACCESS, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_READONLY,
-            PAGE_READWRITE, SEC_COMMIT, SEC_LARGE_PAGES, SE_PRIVILEGE_ENABLED,
+            DUPLICATE_SAME_ACCESS, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_NOACCESS,
+            PAGE_READONLY, PAGE_READWRITE, SEC_COMMIT, SEC_LARGE_PAGES, SE_PRIVILEGE_ENABLED,
             TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES, TOKEN_QUERY,
         },
     },
 };
 
-use crate::{CommonMmapMut, MmapBuilder, MmapRawDescriptor};
+use crate::{
+    Capabilities, Mmap, MmapBuilder, MmapError, MmapRawDescriptor, MmapResult, Protection,
+};
+
+mod region;
+
+pub(crate) use region::*;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct RawDescriptor(pub RawHandle);
@@ -52,6 +55,9 @@ impl AsRef<RawHandle> for RawDescriptor {
     }
 }
 
+/// `AdjustTokenPrivileges` succeeded without granting every privilege
+const ERROR_NOT_ALL_ASSIGNED: i32 = 1300;
+
 fn allocation_granularity() -> usize {
     unsafe {
         let mut info = std::mem::zeroed();
@@ -60,8 +66,52 @@ fn allocation_granularity() -> usize {
     }
 }
 
+fn page_size() -> usize {
+    unsafe {
+        let mut info = std::mem::zeroed();
+        GetSystemInfo(&mut info);
+        info.dwPageSize as usize
+    }
+}
+
+/// make freshly written code visible to instruction fetches
+pub(crate) fn flush_icache(ptr: *const u8, len: usize) {
+    unsafe {
+        FlushInstructionCache(GetCurrentProcess(), ptr as *const c_void, len as SIZE_T);
+    }
+}
+
+/// make `file` at least `len` bytes long, ntfs allocates the clusters when
+/// the end of file is moved, so a full disk fails here
+pub(crate) fn preallocate(file: &std::fs::File, len: u64) -> MmapResult<()> {
+    let file_len = file
+        .metadata()
+        .map_err(|err| MmapError::from_io("GetFileInformationByHandle", err))?
+        .len();
+    if file_len < len {
+        file.set_len(len)
+            .map_err(|err| MmapError::from_io("SetEndOfFile", err))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn platform_capabilities() -> Capabilities {
+    let large_page_size = unsafe { GetLargePageMinimum() };
+    Capabilities {
+        page_size: page_size(),
+        huge_page_sizes: if large_page_size != 0 {
+            vec![large_page_size]
+        } else {
+            Vec::new()
+        },
+        map_stack: false,
+        populate: false,
+        advice: false,
+    }
+}
+
 impl MmapBuilder {
-    pub fn build(self) -> std::io::Result<Mmap> {
+    pub(crate) fn map(self) -> MmapResult<MmapInner> {
         // TODO: large page + offset
         // create access and protection flags
         let (access, protection) = match (self.read, self.write, self.execute) {
@@ -85,9 +135,8 @@ impl MmapBuilder {
                 let protect = PAGE_READONLY;
                 Ok((access, protect))
             }
-            _ => Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "invalid access",
+            _ => Err(MmapError::InvalidConfig(
+                "read access is required and can only be combined with write and execute",
             )),
         }?;
 
@@ -95,9 +144,10 @@ impl MmapBuilder {
         let (access, protection) = if self.huge_page {
             let large_page_size = unsafe { GetLargePageMinimum() };
             if large_page_size != 0 {
-                println!("Huge pages are supported size {}", large_page_size);
                 (access | FILE_MAP_LARGE_PAGES, protection | SEC_LARGE_PAGES)
             } else {
+                #[cfg(feature = "log")]
+                log::debug!("large pages are not supported, falling back to regular pages");
                 (access, protection)
             }
         } else {
@@ -111,16 +161,22 @@ impl MmapBuilder {
             access
         };
 
+        // windows refuses to map zero bytes just like unix, so hand out an
+        // empty mapping instead
+        if self.len == 0 {
+            return Ok(MmapInner {
+                handle: None,
+                base: std::ptr::null_mut(),
+                ptr: std::ptr::NonNull::<u8>::dangling().as_ptr() as *mut c_void,
+                len: 0,
+                page_size: page_size(),
+            });
+        }
+
         if let Some(desc) = self.descriptor {
             let alignment = self.offset % allocation_granularity() as u64;
             let aligned_offset = self.offset - alignment as u64;
-            let aligned_len = self.len.max(1) + alignment as usize;
-            if aligned_len == 0 {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "invalid len",
-                ));
-            }
+            let aligned_len = self.len + alignment as usize;
 
             unsafe {
                 let mapping = CreateFileMappingW(
@@ -132,7 +188,7 @@ impl MmapBuilder {
                     std::ptr::null(),
                 );
                 if mapping.is_null() {
-                    return Err(std::io::Error::last_os_error());
+                    return Err(MmapError::last_os_error("CreateFileMappingW"));
                 }
 
                 let ptr = MapViewOfFile(
@@ -144,7 +200,7 @@ impl MmapBuilder {
                 );
                 CloseHandle(mapping);
                 if ptr.is_null() {
-                    return Err(std::io::Error::last_os_error());
+                    return Err(MmapError::last_os_error("MapViewOfFile"));
                 }
 
                 let mut new_handle = 0 as RawHandle;
@@ -159,21 +215,23 @@ impl MmapBuilder {
                     DUPLICATE_SAME_ACCESS,
                 ) != 0
                 {
-                    Ok(Mmap {
+                    Ok(MmapInner {
                         handle: Some(new_handle),
+                        base: ptr,
                         ptr: ptr.offset(alignment as isize),
                         len: self.len,
+                        page_size: page_size(),
                     })
                 } else {
+                    let err = MmapError::last_os_error("DuplicateHandle");
                     UnmapViewOfFile(ptr);
-                    Err(std::io::Error::last_os_error())
+                    Err(err)
                 }
             }
         }
         // then is anonymous mapping
         else {
-            // Ensure a non-zero length for the underlying mapping
-            let mapped_len = self.len.max(1);
+            let mapped_len = self.len;
             unsafe {
                 // Create a mapping and view with maximum access permissions, then use
                 // `VirtualProtect` to set the actual `Protection`. This way, we
@@ -186,11 +244,14 @@ impl MmapBuilder {
                     {
                         // get token
                         let mut token = std::ptr::null_mut();
-                        OpenProcessToken(
+                        if OpenProcessToken(
                             GetCurrentProcess(),
                             TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY,
                             &mut token,
-                        );
+                        ) == 0
+                        {
+                            return Err(MmapError::last_os_error("OpenProcessToken"));
+                        }
                         // get luid
                         let mut tp: TOKEN_PRIVILEGES = std::mem::zeroed();
                         if LookupPrivilegeValueW(
@@ -201,7 +262,9 @@ impl MmapBuilder {
                             &mut tp.Privileges[0].Luid,
                         ) == 0
                         {
-                            return Err(std::io::Error::last_os_error());
+                            let err = MmapError::last_os_error("LookupPrivilegeValueW");
+                            CloseHandle(token);
+                            return Err(err);
                         }
                         tp.PrivilegeCount = 1;
                         tp.Privileges[0].Attributes = SE_PRIVILEGE_ENABLED;
@@ -215,11 +278,16 @@ impl MmapBuilder {
                             std::ptr::null_mut(),
                         ) == 0
                         {
-                            return Err(std::io::Error::last_os_error());
+                            let err = MmapError::last_os_error("AdjustTokenPrivileges");
+                            CloseHandle(token);
+                            return Err(err);
                         }
-                        let err = std::io::Error::last_os_error();
-                        println!("set os privilege status: {:?}", err);
+                        // succeeding does not mean the privilege was granted
+                        let status = std::io::Error::last_os_error().raw_os_error();
                         CloseHandle(token);
+                        if status == Some(ERROR_NOT_ALL_ASSIGNED) {
+                            return Err(MmapError::InsufficientPrivilege("SeLockMemoryPrivilege"));
+                        }
                     }
                     let mapping = CreateFileMappingW(
                         INVALID_HANDLE_VALUE,
@@ -230,7 +298,7 @@ impl MmapBuilder {
                         std::ptr::null(),
                     );
                     if mapping.is_null() {
-                        return Err(std::io::Error::last_os_error());
+                        return Err(MmapError::last_os_error("CreateFileMappingW"));
                     }
 
                     let access = FILE_MAP_ALL_ACCESS;
@@ -239,19 +307,22 @@ impl MmapBuilder {
                     CloseHandle(mapping);
 
                     if ptr.is_null() {
-                        return Err(std::io::Error::last_os_error());
+                        return Err(MmapError::last_os_error("MapViewOfFile"));
                     }
 
                     let mut old = 0;
                     if VirtualProtect(ptr, mapped_len as SIZE_T, protection, &mut old) != 0 {
-                        Ok(Mmap {
+                        Ok(MmapInner {
                             handle: None,
+                            base: ptr,
                             ptr,
                             len: self.len,
+                            page_size: GetLargePageMinimum(),
                         })
                     } else {
+                        let err = MmapError::last_os_error("VirtualProtect");
                         UnmapViewOfFile(ptr);
-                        Err(std::io::Error::last_os_error())
+                        Err(err)
                     }
                 } else {
                     let mapping = CreateFileMappingW(
@@ -263,7 +334,7 @@ impl MmapBuilder {
                         std::ptr::null(),
                     );
                     if mapping.is_null() {
-                        return Err(std::io::Error::last_os_error());
+                        return Err(MmapError::last_os_error("CreateFileMappingW"));
                     }
                     let access = FILE_MAP_ALL_ACCESS | FILE_MAP_EXECUTE;
 
@@ -271,19 +342,22 @@ impl MmapBuilder {
                     CloseHandle(mapping);
 
                     if ptr.is_null() {
-                        return Err(std::io::Error::last_os_error());
+                        return Err(MmapError::last_os_error("MapViewOfFile"));
                     }
 
                     let mut old = 0;
                     if VirtualProtect(ptr, mapped_len as SIZE_T, protection, &mut old) != 0 {
-                        Ok(Mmap {
+                        Ok(MmapInner {
                             handle: None,
+                            base: ptr,
                             ptr,
                             len: self.len,
+                            page_size: page_size(),
                         })
                     } else {
+                        let err = MmapError::last_os_error("VirtualProtect");
                         UnmapViewOfFile(ptr);
-                        Err(std::io::Error::last_os_error())
+                        Err(err)
                     }
                 }
             }
@@ -292,89 +366,148 @@ impl MmapBuilder {
 }
 
 pub trait MmapBuilderWindowsExt {
-    fn build_cow(self) -> std::io::Result<Mmap>;
-}
-
-#[derive(Clone)]
-pub struct Mmap {
-    handle: Option<RawHandle>,
-    pub(crate) ptr: *mut c_void,
-    pub(crate) len: usize,
-}
-
-impl Mmap {
-    pub fn as_mut(&self) -> MmapMut {
-        MmapMut {
-            handle: self.handle,
-            ptr: self.ptr,
-            len: self.len,
-        }
-    }
+    fn build_cow(self) -> MmapResult<Mmap>;
 }
 
-#[derive(Clone, Copy)]
-pub struct MmapMut {
+pub(crate) struct MmapInner {
     handle: Option<RawHandle>,
+    /// address returned by `MapViewOfFile`, aligned to the allocation
+    /// granularity
+    base: *mut c_void,
+    /// the window requested by the user, starts `offset % granularity` bytes
+    /// after `base`
     ptr: *mut c_void,
     len: usize,
+    /// the page size actually backing the mapping
+    page_size: usize,
 }
 
-impl Drop for Mmap {
-    fn drop(&mut self) {
-        let alignment = self.ptr as usize % allocation_granularity();
-        // Any errors during unmapping/closing are ignored as the only way
-        // to report them would be through panicking which is highly discouraged
-        // in Drop impls, c.f. https://github.com/rust-lang/lang-team/issues/97
-        unsafe {
-            let ptr = self.ptr.offset(-(alignment as isize));
-            UnmapViewOfFile(ptr);
+impl MmapInner {
+    pub(crate) fn ptr(&self) -> *mut u8 {
+        self.ptr as *mut u8
+    }
 
-            if let Some(handle) = self.handle {
-                CloseHandle(handle as _);
-            }
-        }
+    pub(crate) fn len(&self) -> usize {
+        self.len
     }
-}
 
-impl CommonMmapMut for MmapMut {
-    fn as_slice(&mut self) -> &mut [u8] {
-        unsafe { slice::from_raw_parts_mut(self.ptr as *mut _, self.len) }
+    pub(crate) fn page_size(&self) -> usize {
+        self.page_size
     }
 
-    fn flush_all(&self) -> std::io::Result<()> {
-        self.flush_range(0, self.len)
+    fn virtual_protect(
+        &self,
+        ptr: *mut c_void,
+        len: usize,
+        protection: Protection,
+    ) -> MmapResult<()> {
+        let protection = match protection {
+            Protection::None => PAGE_NOACCESS,
+            Protection::Read => PAGE_READONLY,
+            Protection::ReadWrite => PAGE_READWRITE,
+            Protection::ReadExecute => PAGE_EXECUTE_READ,
+            Protection::ReadWriteExecute => PAGE_EXECUTE_READWRITE,
+        };
+        let mut old = 0;
+        if unsafe { VirtualProtect(ptr, len as SIZE_T, protection, &mut old) } != 0 {
+            Ok(())
+        } else {
+            Err(MmapError::last_os_error("VirtualProtect"))
+        }
     }
 
-    fn flush_all_non_blocking(&self) -> std::io::Result<()> {
-        self.flush_range_non_blocking(0, self.len)
+    /// the caller guarantees the range is not accessed against `protection`
+    pub(crate) fn protect(
+        &self,
+        offset: usize,
+        len: usize,
+        protection: Protection,
+    ) -> MmapResult<()> {
+        if offset.checked_add(len).is_none_or(|end| end > self.len) {
+            return Err(MmapError::OutOfRange {
+                start: offset as u64,
+                end: (offset as u64).saturating_add(len as u64),
+                len: self.len as u64,
+            });
+        }
+        let addr = self.ptr as usize + offset;
+        if !addr.is_multiple_of(self.page_size) {
+            return Err(MmapError::Misaligned {
+                what: "protect address",
+                value: addr as u64,
+                alignment: self.page_size,
+            });
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        self.virtual_protect(addr as *mut c_void, len, protection)
     }
 
-    fn flush_range(&self, offset: usize, len: usize) -> std::io::Result<()> {
-        self.flush_range_non_blocking(offset, len)?;
+    pub(crate) fn protect_all(&self, protection: Protection) -> MmapResult<()> {
+        if self.base.is_null() {
+            return Ok(());
+        }
+        let len = self.ptr as usize - self.base as usize + self.len;
+        self.virtual_protect(self.base, len, protection)
+    }
+
+    pub(crate) fn flush(&self, offset: usize, len: usize) -> MmapResult<()> {
+        self.flush_non_blocking(offset, len)?;
         if self.handle.is_some() {
             self.block_on_flush()?;
         }
         Ok(())
     }
 
-    fn flush_range_non_blocking(&self, offset: usize, len: usize) -> std::io::Result<()> {
+    pub(crate) fn flush_non_blocking(&self, offset: usize, len: usize) -> MmapResult<()> {
+        if offset.checked_add(len).is_none_or(|end| end > self.len) {
+            return Err(MmapError::OutOfRange {
+                start: offset as u64,
+                end: (offset as u64).saturating_add(len as u64),
+                len: self.len as u64,
+            });
+        }
+        // a zero length would flush the whole view
+        if len == 0 {
+            return Ok(());
+        }
         // i know this looks too C
         if unsafe { FlushViewOfFile(self.ptr.add(offset), len as SIZE_T) } != 0 {
             Ok(())
         } else {
-            Err(std::io::Error::last_os_error())
+            Err(MmapError::last_os_error("FlushViewOfFile"))
         }
     }
 
-    fn block_on_flush(&self) -> std::io::Result<()> {
+    pub(crate) fn block_on_flush(&self) -> MmapResult<()> {
         if let Some(handle) = self.handle {
             if unsafe { FlushFileBuffers(handle as _) } != 0 {
                 Ok(())
             } else {
-                Err(std::io::Error::last_os_error())
+                Err(MmapError::last_os_error("FlushFileBuffers"))
             }
         } else {
-            Err(std::io::Error::new(std::io::ErrorKind::Other, "no handle"))
+            Err(MmapError::Unsupported(
+                "anonymous mappings have no handle to flush",
+            ))
+        }
+    }
+}
+
+impl Drop for MmapInner {
+    fn drop(&mut self) {
+        // Any errors during unmapping/closing are ignored as the only way
+        // to report them would be through panicking which is highly discouraged
+        // in Drop impls, c.f. https://github.com/rust-lang/lang-team/issues/97
+        unsafe {
+            if !self.base.is_null() {
+                UnmapViewOfFile(self.base);
+            }
+
+            if let Some(handle) = self.handle {
+                CloseHandle(handle as _);
+            }
         }
     }
 }
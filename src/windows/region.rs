@@ -0,0 +1,83 @@
+use winapi::{
+    ctypes::c_void,
+    shared::basetsd::SIZE_T,
+    um::{
+        memoryapi::{VirtualAlloc, VirtualFree},
+        winnt::{
+            MEM_COMMIT, MEM_DECOMMIT, MEM_RELEASE, MEM_RESERVE, MEM_RESET, PAGE_NOACCESS,
+            PAGE_READWRITE,
+        },
+    },
+};
+
+use super::page_size;
+use crate::{MmapError, MmapResult};
+
+pub(crate) struct RegionInner {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl RegionInner {
+    pub(crate) fn reserve(len: usize) -> MmapResult<RegionInner> {
+        let len = len.next_multiple_of(page_size());
+        let ptr = unsafe {
+            VirtualAlloc(
+                std::ptr::null_mut(),
+                len as SIZE_T,
+                MEM_RESERVE,
+                PAGE_NOACCESS,
+            )
+        };
+        if ptr.is_null() {
+            return Err(MmapError::last_os_error("VirtualAlloc"));
+        }
+        Ok(RegionInner { ptr, len })
+    }
+
+    pub(crate) fn ptr(&self) -> *mut u8 {
+        self.ptr as *mut u8
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn page_size(&self) -> usize {
+        page_size()
+    }
+
+    fn virtual_alloc(&self, offset: usize, len: usize, kind: u32, protect: u32) -> MmapResult<()> {
+        let ptr = unsafe { VirtualAlloc(self.ptr.add(offset), len as SIZE_T, kind, protect) };
+        if ptr.is_null() {
+            Err(MmapError::last_os_error("VirtualAlloc"))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn commit(&self, offset: usize, len: usize) -> MmapResult<()> {
+        self.virtual_alloc(offset, len, MEM_COMMIT, PAGE_READWRITE)
+    }
+
+    pub(crate) fn decommit(&self, offset: usize, len: usize) -> MmapResult<()> {
+        if unsafe { VirtualFree(self.ptr.add(offset), len as SIZE_T, MEM_DECOMMIT) } != 0 {
+            Ok(())
+        } else {
+            Err(MmapError::last_os_error("VirtualFree"))
+        }
+    }
+
+    pub(crate) fn reset(&self, offset: usize, len: usize) -> MmapResult<()> {
+        // the protection is ignored but has to be valid
+        self.virtual_alloc(offset, len, MEM_RESET, PAGE_NOACCESS)
+    }
+}
+
+impl Drop for RegionInner {
+    fn drop(&mut self) {
+        unsafe {
+            VirtualFree(self.ptr, 0, MEM_RELEASE);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use xmmap::{LogPosition, MmapError, MmapLog};
+
+const SEGMENT_LEN: usize = 4096;
+
+fn records(log: &MmapLog) -> Vec<(LogPosition, Vec<u8>)> {
+    let mut records = Vec::new();
+    log.for_each(|position, record| records.push((position, record.to_vec())))
+        .unwrap();
+    records
+}
+
+fn segment(dir: &Path, segment: u64) -> PathBuf {
+    dir.join(format!("{:020}.log", segment))
+}
+
+#[test]
+fn reopen_zeroes_a_torn_tail() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut log = MmapLog::open(dir.path(), SEGMENT_LEN).unwrap();
+    log.append(b"first").unwrap();
+    log.append(b"second").unwrap();
+    log.flush().unwrap();
+    let end = log.position();
+    drop(log);
+
+    // half a frame and some payload, as a crash in the middle of an append
+    // leaves it
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(segment(dir.path(), 0))
+        .unwrap();
+    file.seek(SeekFrom::Start(end.offset as u64)).unwrap();
+    file.write_all(&[9, 0, 0, 0, 0xde, 0xad, b'x', b'y'])
+        .unwrap();
+    drop(file);
+
+    let mut log = MmapLog::open(dir.path(), SEGMENT_LEN).unwrap();
+    assert_eq!(log.position(), end);
+    let contents = fs::read(segment(dir.path(), 0)).unwrap();
+    assert!(contents[end.offset..].iter().all(|&byte| byte == 0));
+    let expected: Vec<_> = [(0, &b"first"[..]), (13, &b"second"[..])]
+        .into_iter()
+        .map(|(offset, record)| (LogPosition { segment: 0, offset }, record.to_vec()))
+        .collect();
+    assert_eq!(records(&log), expected);
+
+    // appends continue where the valid records end
+    assert_eq!(log.append(b"third").unwrap(), end);
+    log.flush().unwrap();
+    drop(log);
+    let log = MmapLog::open(dir.path(), SEGMENT_LEN).unwrap();
+    assert_eq!(records(&log).len(), 3);
+}
+
+#[test]
+fn records_roll_over_into_new_segments() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut log = MmapLog::open(dir.path(), SEGMENT_LEN).unwrap();
+    let mut appended = Vec::new();
+    for i in 0..100u32 {
+        let record = vec![i as u8; 100 + i as usize];
+        appended.push((log.append(&record).unwrap(), record));
+    }
+    log.flush().unwrap();
+    assert!(log.position().segment > 1);
+    assert_eq!(records(&log), appended);
+    drop(log);
+
+    let mut log = MmapLog::open(dir.path(), SEGMENT_LEN).unwrap();
+    assert_eq!(log.position().segment, appended.last().unwrap().0.segment);
+    assert_eq!(records(&log), appended);
+
+    // a record that fills a whole segment still fits, one byte more does not
+    let max = log.max_record_len();
+    let position = log.append(&vec![1; max]).unwrap();
+    assert_eq!(position.offset, 0);
+    assert!(matches!(
+        log.append(&vec![1; max + 1]),
+        Err(MmapError::OutOfRange { .. })
+    ));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn segments_are_not_sparse() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let _log = MmapLog::open(dir.path(), 1 << 20).unwrap();
+    let metadata = fs::metadata(segment(dir.path(), 0)).unwrap();
+    assert_eq!(metadata.len(), 1 << 20);
+    assert!(metadata.blocks() * 512 >= 1 << 20);
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "bytes")]
+
+use std::fs;
+
+use bytes::Bytes;
+use xmmap::{CommonMmapBuilder, CommonMmapMut, Mmap};
+
+#[test]
+fn mappings_convert_without_copying() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file");
+    fs::write(&path, b"hello world").unwrap();
+    let map = Mmap::open(&path).unwrap();
+    let ptr = map.as_ptr();
+
+    let bytes = Bytes::from(map);
+    assert_eq!(bytes.as_ptr(), ptr);
+    assert_eq!(&bytes[..], b"hello world");
+
+    // the last clone keeps the mapping alive
+    let clone = bytes.clone();
+    let hello = bytes.slice(..5);
+    drop(bytes);
+    drop(clone);
+    assert_eq!(hello.as_ptr(), ptr);
+    assert_eq!(&hello[..], b"hello");
+}
+
+#[test]
+fn writable_mappings_convert_without_copying() {
+    let mut map = Mmap::builder()
+        .set_read(true)
+        .set_len(4)
+        .build_mut()
+        .unwrap();
+    map.as_mut_slice().copy_from_slice(b"abcd");
+    let ptr = map.as_ptr();
+    let bytes = Bytes::from(map);
+    assert_eq!(bytes.as_ptr(), ptr);
+    assert_eq!(&bytes[..], b"abcd");
+}
+
+#[test]
+fn views_convert_without_copying() {
+    let mut map = Mmap::builder()
+        .set_read(true)
+        .set_len(100)
+        .build_mut()
+        .unwrap();
+    map.as_mut_slice()[60..].fill(7);
+    let (head, tail) = map.into_view_mut().into_view().split_at(60).unwrap();
+    let ptr = tail.as_ptr();
+
+    let bytes = Bytes::from(tail);
+    assert_eq!(bytes.as_ptr(), ptr);
+    assert_eq!(bytes.len(), 40);
+    // the other view and the clone both keep the mapping alive
+    let clone = bytes.clone();
+    drop(bytes);
+    drop(head);
+    assert_eq!(clone.as_ptr(), ptr);
+    assert!(clone.iter().all(|&byte| byte == 7));
+}
@@ -0,0 +1,17 @@
+use std::fs;
+
+/// the permissions `/proc/self/maps` lists for the page at `addr`
+pub fn perms(addr: *const u8) -> String {
+    let addr = addr as usize;
+    fs::read_to_string("/proc/self/maps")
+        .unwrap()
+        .lines()
+        .find_map(|line| {
+            let (range, rest) = line.split_once(' ')?;
+            let (start, end) = range.split_once('-')?;
+            let start = usize::from_str_radix(start, 16).ok()?;
+            let end = usize::from_str_radix(end, 16).ok()?;
+            (start..end).contains(&addr).then(|| rest[..3].to_owned())
+        })
+        .unwrap()
+}
@@ -0,0 +1,116 @@
+use std::{
+    fs,
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
+};
+
+use xmmap::{CommonMmapBuilder, CommonMmapMut, Mmap, MmapCursor, MmapMut};
+
+/// an anonymous mapping holding `bytes`
+fn map(bytes: &[u8]) -> MmapMut {
+    let mut map = Mmap::builder()
+        .set_read(true)
+        .set_len(bytes.len())
+        .build_mut()
+        .unwrap();
+    map.as_mut_slice().copy_from_slice(bytes);
+    map
+}
+
+#[test]
+fn reads_stop_at_the_end() {
+    let map = map(b"hello world");
+    let mut cursor = map.reader();
+    let mut buf = [0; 5];
+    cursor.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    assert_eq!(cursor.position(), 5);
+
+    let mut rest = Vec::new();
+    assert_eq!(cursor.read_to_end(&mut rest).unwrap(), 6);
+    assert_eq!(rest, b" world");
+    assert_eq!(cursor.read(&mut buf).unwrap(), 0);
+    assert!(cursor.read_exact(&mut buf).is_err());
+}
+
+#[test]
+fn buffered_reads_borrow_the_mapping() {
+    let map = map(b"one\ntwo\nthree");
+    let mut cursor = map.reader();
+    let buf = cursor.fill_buf().unwrap();
+    assert_eq!(buf, b"one\ntwo\nthree");
+    // no copy, the buffer is the mapping
+    assert_eq!(buf.as_ptr(), map.as_ptr());
+    cursor.consume(4);
+    assert_eq!(cursor.fill_buf().unwrap(), b"two\nthree");
+
+    let lines: Vec<_> = cursor.lines().map(Result::unwrap).collect();
+    assert_eq!(lines, ["two", "three"]);
+
+    let mut cursor = map.reader();
+    cursor.set_position(100);
+    assert!(cursor.fill_buf().unwrap().is_empty());
+}
+
+#[test]
+fn seeks_move_anywhere_but_before_the_start() {
+    let map = map(b"0123456789");
+    let mut cursor = map.reader();
+    assert_eq!(cursor.seek(SeekFrom::End(-3)).unwrap(), 7);
+    assert_eq!(cursor.fill_buf().unwrap(), b"789");
+    assert_eq!(cursor.seek(SeekFrom::Current(-5)).unwrap(), 2);
+    assert_eq!(cursor.stream_position().unwrap(), 2);
+
+    let err = cursor.seek(SeekFrom::Current(-3)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(cursor.seek(SeekFrom::End(-11)).is_err());
+    // a failed seek keeps the position
+    assert_eq!(cursor.position(), 2);
+
+    // past the end reads return nothing
+    assert_eq!(cursor.seek(SeekFrom::End(5)).unwrap(), 15);
+    assert_eq!(cursor.read(&mut [0; 4]).unwrap(), 0);
+    assert_eq!(cursor.seek(SeekFrom::Start(9)).unwrap(), 9);
+    assert_eq!(cursor.read(&mut [0; 4]).unwrap(), 1);
+}
+
+#[test]
+fn writes_are_cut_at_the_end() {
+    let mut map = map(&[0; 8]);
+    let mut cursor = map.writer();
+    assert_eq!(cursor.write(b"abc").unwrap(), 3);
+    cursor.seek(SeekFrom::Start(6)).unwrap();
+    // a short write
+    assert_eq!(cursor.write(b"xyz").unwrap(), 2);
+    assert_eq!(cursor.position(), 8);
+    assert_eq!(cursor.write(b"xyz").unwrap(), 0);
+    let err = cursor.write_all(b"xyz").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+
+    cursor.set_position(100);
+    assert_eq!(cursor.write(b"xyz").unwrap(), 0);
+    assert_eq!(map[..], *b"abc\0\0\0xy");
+}
+
+#[cfg(unix)]
+#[test]
+fn auto_grow_extends_files_only() {
+    let mut anon = Mmap::builder()
+        .set_read(true)
+        .set_len(10)
+        .build_mut()
+        .unwrap();
+    let mut cursor = MmapCursor::new(&mut anon).set_auto_grow(true);
+    assert!(cursor.write_all(&[1; 100_000]).is_err());
+    assert_eq!(anon.len(), 10);
+    assert_eq!(anon[..], [0; 10]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file");
+    fs::write(&path, [0; 10]).unwrap();
+    let mut map = MmapMut::open(&path).unwrap();
+    let mut cursor = MmapCursor::new(&mut map).set_auto_grow(true);
+    cursor.write_all(&[1; 100_000]).unwrap();
+    cursor.flush().unwrap();
+    drop(map);
+    assert_eq!(fs::read(&path).unwrap(), [1; 100_000]);
+}
@@ -0,0 +1,85 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use xmmap::{DualExecutableMmap, ExecutableMmapMut, capabilities};
+
+use common::perms;
+
+/// `return 42` followed by the same function returning 7
+#[cfg(target_arch = "x86_64")]
+const CODE: [&[u8]; 2] = [
+    // mov eax, 42; ret
+    &[0xb8, 42, 0, 0, 0, 0xc3],
+    // mov eax, 7; ret
+    &[0xb8, 7, 0, 0, 0, 0xc3],
+];
+#[cfg(target_arch = "aarch64")]
+const CODE: [&[u8]; 2] = [
+    // mov w0, #42; ret
+    &[0x40, 0x05, 0x80, 0x52, 0xc0, 0x03, 0x5f, 0xd6],
+    // mov w0, #7; ret
+    &[0xe0, 0x00, 0x80, 0x52, 0xc0, 0x03, 0x5f, 0xd6],
+];
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn call(ptr: *const u8) -> u32 {
+    let f: extern "C" fn() -> u32 = unsafe { std::mem::transmute(ptr) };
+    f()
+}
+
+#[test]
+fn code_is_never_writable_and_executable() {
+    let mut code = ExecutableMmapMut::new(capabilities().page_size).unwrap();
+    assert_eq!(perms(code.as_ptr()), "rw-");
+    code[..3].copy_from_slice(&[1, 2, 3]);
+
+    let code = code.make_executable().unwrap();
+    assert_eq!(perms(code.as_ptr()), "r-x");
+    assert_eq!(code[..3], [1, 2, 3]);
+
+    let mut code = code.make_mut().unwrap();
+    assert_eq!(perms(code.as_ptr()), "rw-");
+    code[3] = 4;
+    assert_eq!(code[..4], [1, 2, 3, 4]);
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+#[test]
+fn emitted_code_runs_and_is_patched() {
+    let mut code = ExecutableMmapMut::new(capabilities().page_size).unwrap();
+    code[..CODE[0].len()].copy_from_slice(CODE[0]);
+    let code = code.make_executable().unwrap();
+    assert_eq!(call(code.as_ptr()), 42);
+
+    let mut code = code.make_mut().unwrap();
+    code[..CODE[1].len()].copy_from_slice(CODE[1]);
+    let code = code.make_executable().unwrap();
+    assert_eq!(call(code.as_ptr()), 7);
+}
+
+#[test]
+fn dual_mappings_alias() {
+    let mut code = DualExecutableMmap::new(capabilities().page_size).unwrap();
+    let rw = code.as_mut_slice().as_ptr();
+    assert_ne!(rw, code.as_ptr());
+    assert_eq!(perms(rw), "rw-");
+    assert_eq!(perms(code.as_ptr()), "r-x");
+
+    code.as_mut_slice()[..3].copy_from_slice(&[1, 2, 3]);
+    code.flush_icache(0, 3).unwrap();
+    let rx = unsafe { std::slice::from_raw_parts(code.as_ptr(), 3) };
+    assert_eq!(rx, [1, 2, 3]);
+    assert!(code.flush_icache(1, capabilities().page_size).is_err());
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+#[test]
+fn dual_mappings_are_patched_while_executable() {
+    let mut code = DualExecutableMmap::new(capabilities().page_size).unwrap();
+    for (code_bytes, result) in CODE.iter().zip([42, 7]) {
+        code.as_mut_slice()[..code_bytes.len()].copy_from_slice(code_bytes);
+        code.flush_icache(0, code_bytes.len()).unwrap();
+        assert_eq!(call(code.as_ptr()), result);
+    }
+}
@@ -0,0 +1,78 @@
+#![cfg(unix)]
+
+use std::{
+    fs::{self, File},
+    os::unix::prelude::{AsFd, AsRawFd},
+};
+
+use xmmap::{CommonMmapBuilder, FdMmap, Mmap, MmapBuilder, MmapMut};
+
+#[test]
+fn file_mappings_outlive_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file");
+    fs::write(&path, b"hello").unwrap();
+    let file = File::options().read(true).write(true).open(&path).unwrap();
+    let map = MmapBuilder::from_file(&file).unwrap().build_mut().unwrap();
+    drop(file);
+
+    let map = FdMmap::<MmapMut>::try_from(map).ok().unwrap();
+    assert_ne!(map.as_raw_fd(), -1);
+    // the duplicate still refers to the file
+    let dup = File::from(map.as_fd().try_clone_to_owned().unwrap());
+    assert_eq!(dup.metadata().unwrap().len(), 5);
+    assert_eq!(&map.into_inner()[..], b"hello");
+}
+
+#[test]
+fn anonymous_mappings_have_no_descriptor() {
+    let mut map = Mmap::builder()
+        .set_read(true)
+        .set_len(10)
+        .build_mut()
+        .unwrap();
+    map[0] = 1;
+    assert!(map.fd().is_none());
+    // the mapping is handed back
+    let map = FdMmap::<MmapMut>::try_from(map).err().unwrap();
+    assert_eq!(map[0], 1);
+    let map = map.make_read_only().unwrap();
+    assert!(FdMmap::<Mmap>::try_from(map).is_err());
+}
+
+#[test]
+fn descriptors_map_the_same_object() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file");
+    fs::write(&path, [0; 16]).unwrap();
+    let file = File::options().read(true).write(true).open(&path).unwrap();
+    let mut map =
+        FdMmap::<MmapMut>::try_from(MmapBuilder::from_file(&file).unwrap().build_mut().unwrap())
+            .ok()
+            .unwrap();
+    let other = MmapBuilder::from_file(&File::from(map.as_fd().try_clone_to_owned().unwrap()))
+        .unwrap()
+        .build()
+        .unwrap();
+    map[3] = 9;
+    assert_eq!(other[3], 9);
+}
+
+#[test]
+fn swapped_mappings_keep_the_descriptor() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file");
+    fs::write(&path, [0; 16]).unwrap();
+    let mut map = FdMmap::<MmapMut>::try_from(MmapMut::open(&path).unwrap())
+        .ok()
+        .unwrap();
+    let anon = Mmap::builder()
+        .set_read(true)
+        .set_len(10)
+        .build_mut()
+        .unwrap();
+    drop(std::mem::replace(&mut *map, anon));
+    // still the file the wrapper was converted from
+    let dup = File::from(map.as_fd().try_clone_to_owned().unwrap());
+    assert_eq!(dup.metadata().unwrap().len(), 16);
+}
@@ -0,0 +1,64 @@
+#![cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+
+use std::fs::{self, OpenOptions};
+
+use xmmap::{Mmap, MmapError, capabilities};
+
+#[test]
+fn copies_from_a_truncated_file_fault() {
+    let page = capabilities().page_size;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file");
+    let contents: Vec<u8> = (0..3 * page).map(|i| i as u8).collect();
+    fs::write(&path, &contents).unwrap();
+    let map = Mmap::open(&path).unwrap();
+
+    let file = OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(page as u64).unwrap();
+
+    // the copy stops at the first page past the end of the file
+    let mut buf = [0; 200];
+    assert!(matches!(
+        map.try_copy_to(page - 100, &mut buf),
+        Err(MmapError::Fault { offset }) if offset == page as u64
+    ));
+    assert_eq!(&buf[..100], &contents[page - 100..page]);
+    assert!(matches!(
+        map.try_read(2 * page..2 * page + 1, |bytes| bytes[0]),
+        Err(MmapError::Fault { offset }) if offset == 2 * page as u64
+    ));
+    // the pages still in the file stay readable
+    map.try_copy_to(0, &mut buf).unwrap();
+    assert_eq!(&buf[..], &contents[..200]);
+    assert_eq!(map[page - 1], contents[page - 1]);
+
+    // once the file is grown again the whole mapping reads, as zeroes
+    file.set_len(3 * page as u64).unwrap();
+    map.try_copy_to(page - 100, &mut buf).unwrap();
+    assert_eq!(&buf[..100], &contents[page - 100..page]);
+    assert!(buf[100..].iter().all(|&byte| byte == 0));
+    assert_eq!(map[3 * page - 1], 0);
+}
+
+#[test]
+fn copies_check_the_range() {
+    let page = capabilities().page_size;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file");
+    fs::write(&path, vec![1; page]).unwrap();
+    let map = Mmap::open(&path).unwrap();
+
+    let mut buf = [0; 2];
+    assert!(matches!(
+        map.try_copy_to(page - 1, &mut buf),
+        Err(MmapError::OutOfRange { .. })
+    ));
+    assert!(matches!(
+        map.try_copy_to(usize::MAX, &mut buf),
+        Err(MmapError::OutOfRange { .. })
+    ));
+    assert_eq!(map.try_read(0..page, |bytes| bytes.len()).unwrap(), page);
+}
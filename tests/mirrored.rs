@@ -0,0 +1,50 @@
+#![cfg(unix)]
+
+use xmmap::{MirroredBuffer, MmapError, capabilities};
+
+#[test]
+fn the_mirror_aliases_the_ring() {
+    let len = capabilities().page_size;
+    let mut buffer = MirroredBuffer::new(len).unwrap();
+    assert_eq!(buffer.len(), len);
+
+    buffer[len - 1] = 0xaa;
+    buffer[0] = 0xbb;
+    let mirror = unsafe { buffer.as_ptr().add(len) };
+    assert_eq!(unsafe { *mirror.add(len - 1) }, 0xaa);
+    assert_eq!(unsafe { *mirror }, 0xbb);
+
+    // a window across the end is one slice running into the mirror
+    let window = buffer.window(len - 1, 2).unwrap();
+    assert_eq!(window, [0xaa, 0xbb]);
+    assert_eq!(window.as_ptr(), unsafe { buffer.as_ptr().add(len - 1) });
+
+    // writes through a wrapping window land at both ends of the ring
+    buffer
+        .window_mut(2 * len - 2, 4)
+        .unwrap()
+        .copy_from_slice(&[1, 2, 3, 4]);
+    assert_eq!(&buffer[len - 2..], [1, 2]);
+    assert_eq!(&buffer[..2], [3, 4]);
+    assert_eq!(buffer.window(len - 2, 4).unwrap(), [1, 2, 3, 4]);
+}
+
+#[test]
+fn windows_and_lengths_are_checked() {
+    let len = capabilities().page_size;
+    let buffer = MirroredBuffer::new(len).unwrap();
+    assert!(matches!(
+        buffer.window(0, len + 1),
+        Err(MmapError::OutOfRange { .. })
+    ));
+    assert_eq!(buffer.window(1, len).unwrap().len(), len);
+
+    assert!(matches!(
+        MirroredBuffer::new(0),
+        Err(MmapError::InvalidConfig(_))
+    ));
+    assert!(matches!(
+        MirroredBuffer::new(len + 1),
+        Err(MmapError::Misaligned { .. })
+    ));
+}
@@ -0,0 +1,85 @@
+use std::fs::{self, File};
+
+#[cfg(unix)]
+use xmmap::Advice;
+use xmmap::{CommonMmapBuilder, CommonMmapMut, Mmap, MmapBuilder, MmapMut};
+
+/// not a multiple of any page size or allocation granularity
+const OFFSET: usize = 65_536 + 123;
+const LEN: usize = 1000;
+
+fn data() -> Vec<u8> {
+    (0..3 * 65_536).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn unaligned_offsets_map_the_requested_window() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file");
+    let data = data();
+    fs::write(&path, &data).unwrap();
+    let file = File::open(&path).unwrap();
+
+    let map = MmapBuilder::from_file(&file)
+        .unwrap()
+        .set_offset(OFFSET as u64)
+        .set_len(LEN)
+        .build()
+        .unwrap();
+    assert_eq!(&map[..], &data[OFFSET..OFFSET + LEN]);
+
+    // without a length the window runs to the end of the file
+    let map = MmapBuilder::from_file(&file)
+        .unwrap()
+        .set_offset(OFFSET as u64)
+        .build()
+        .unwrap();
+    assert_eq!(&map[..], &data[OFFSET..]);
+
+    let map = Mmap::map_file_range(&file, OFFSET as u64..(OFFSET + LEN) as u64).unwrap();
+    assert_eq!(&map[..], &data[OFFSET..OFFSET + LEN]);
+    #[cfg(unix)]
+    {
+        map.advise(0, LEN, Advice::WillNeed).unwrap();
+        map.advise(1, 10, Advice::Random).unwrap();
+        assert!(map.advise(1, LEN, Advice::Normal).is_err());
+    }
+}
+
+#[test]
+fn unaligned_writable_windows_flush() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file");
+    let mut data = data();
+    fs::write(&path, &data).unwrap();
+    let file = File::options().read(true).write(true).open(&path).unwrap();
+
+    for len in [Some(LEN), None] {
+        let builder = MmapBuilder::from_file(&file)
+            .unwrap()
+            .set_offset(OFFSET as u64);
+        let builder = match len {
+            Some(len) => builder.set_len(len),
+            None => builder,
+        };
+        let mut map: MmapMut = builder.build_mut().unwrap();
+        let len = len.unwrap_or(data.len() - OFFSET);
+        assert_eq!(&map[..], &data[OFFSET..OFFSET + len]);
+
+        map[0] = !map[0];
+        map[len - 1] = !map[len - 1];
+        data[OFFSET] = !data[OFFSET];
+        data[OFFSET + len - 1] = !data[OFFSET + len - 1];
+        map.flush_range(0, 1).unwrap();
+        map.flush_range(len - 1, 1).unwrap();
+        map.flush_range(0, len).unwrap();
+        map.flush_range_non_blocking(3, 7).unwrap();
+        map.flush_all().unwrap();
+        assert!(map.flush_range(1, len).is_err());
+        #[cfg(unix)]
+        map.advise(0, len, Advice::Sequential).unwrap();
+        drop(map);
+
+        assert_eq!(fs::read(&path).unwrap(), data);
+    }
+}
@@ -0,0 +1,161 @@
+use xmmap::{CommonMmapBuilder, Mmap, MmapError, MmapMut};
+
+fn map() -> MmapMut {
+    Mmap::builder()
+        .set_read(true)
+        .set_len(64)
+        .build_mut()
+        .unwrap()
+}
+
+#[test]
+fn views_read_and_write_in_place() {
+    let mut map = map();
+    *map.view_mut::<u32>(8).unwrap() = 0x0102_0304;
+    assert_eq!(map[8..12], 0x0102_0304u32.to_ne_bytes());
+    assert_eq!(*map.view::<u32>(8).unwrap(), 0x0102_0304);
+
+    map.view_slice_mut::<u16>(16..24)
+        .unwrap()
+        .copy_from_slice(&[1, 2, 3, 4]);
+    assert_eq!(map.view_slice::<u16>(16..24).unwrap(), [1, 2, 3, 4]);
+    // the last element may end at the end of the mapping
+    assert_eq!(*map.view::<u64>(56).unwrap(), 0);
+    assert!(map.view_slice::<u64>(64..64).unwrap().is_empty());
+}
+
+#[test]
+fn misaligned_views_are_rejected() {
+    let mut map = map();
+    assert!(matches!(
+        map.view::<u32>(1),
+        Err(MmapError::Misaligned { .. })
+    ));
+    assert!(matches!(
+        map.view_mut::<u64>(4),
+        Err(MmapError::Misaligned { .. })
+    ));
+    assert!(matches!(
+        map.view_slice::<u16>(3..7),
+        Err(MmapError::Misaligned { .. })
+    ));
+    // bytes have no alignment
+    assert!(map.view::<u8>(1).is_ok());
+}
+
+#[test]
+fn out_of_range_views_are_rejected() {
+    let mut map = map();
+    assert!(matches!(
+        map.view::<u64>(64),
+        Err(MmapError::OutOfRange { .. })
+    ));
+    assert!(matches!(
+        map.view_mut::<u32>(62),
+        Err(MmapError::OutOfRange { .. })
+    ));
+    assert!(matches!(
+        map.view_slice::<u8>(60..68),
+        Err(MmapError::OutOfRange { .. })
+    ));
+    assert!(matches!(
+        map.view::<u8>(usize::MAX),
+        Err(MmapError::OutOfRange { .. })
+    ));
+}
+
+#[test]
+fn slice_lengths_are_checked() {
+    let mut map = map();
+    // not a whole number of elements
+    assert!(matches!(
+        map.view_slice::<u32>(0..6),
+        Err(MmapError::Misaligned { .. })
+    ));
+    assert!(matches!(
+        map.view_slice_mut::<u64>(0..12),
+        Err(MmapError::Misaligned { .. })
+    ));
+    #[allow(clippy::reversed_empty_ranges)]
+    let reversed = 8..4;
+    assert!(matches!(
+        map.view_slice::<u32>(reversed),
+        Err(MmapError::InvalidConfig(_))
+    ));
+}
+
+#[test]
+fn zero_sized_views() {
+    let map = map();
+    // a single zero sized value is fine, even at the end
+    assert!(map.view::<[u8; 0]>(64).is_ok());
+    assert!(matches!(
+        map.view_slice::<[u8; 0]>(0..0),
+        Err(MmapError::InvalidConfig(_))
+    ));
+}
+
+#[cfg(feature = "zerocopy")]
+#[test]
+fn zerocopy_types_are_pod() {
+    use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+    #[derive(Clone, Copy, Debug, PartialEq, FromBytes, IntoBytes, Immutable)]
+    #[repr(C)]
+    struct Entry {
+        key: u32,
+        value: u32,
+    }
+
+    xmmap::zerocopy_pod!(Entry);
+
+    let mut map = map();
+    *map.view_mut::<Entry>(8).unwrap() = Entry { key: 1, value: 2 };
+    assert_eq!(map.view_slice::<u32>(8..16).unwrap(), [1, 2]);
+    assert_eq!(
+        map.view_slice::<Entry>(8..16).unwrap(),
+        [Entry { key: 1, value: 2 }]
+    );
+}
+
+#[cfg(feature = "bytemuck")]
+#[test]
+fn bytemuck_types_are_pod() {
+    use bytemuck::{Pod, Zeroable};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+    #[repr(C)]
+    struct Entry {
+        key: u32,
+        value: u32,
+    }
+
+    xmmap::bytemuck_pod!(Entry);
+
+    let mut map = map();
+    *map.view_mut::<Entry>(8).unwrap() = Entry { key: 1, value: 2 };
+    assert_eq!(map.view_slice::<u32>(8..16).unwrap(), [1, 2]);
+    assert_eq!(
+        map.view_slice::<Entry>(8..16).unwrap(),
+        [Entry { key: 1, value: 2 }]
+    );
+}
+
+/// derives `bytemuck::Pod` but implements `Pod` by hand, which keeps
+/// compiling whatever features are enabled
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct Manual {
+    a: u16,
+    b: u16,
+}
+
+unsafe impl xmmap::Pod for Manual {}
+
+#[test]
+fn manual_impls_are_pod() {
+    let mut map = map();
+    *map.view_mut::<Manual>(4).unwrap() = Manual { a: 1, b: 2 };
+    assert_eq!(map.view_slice::<u16>(4..8).unwrap(), [1, 2]);
+    assert_eq!(map.view::<Manual>(4).unwrap().b, 2);
+}
@@ -0,0 +1,64 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use xmmap::{CommonMmapBuilder, CommonMmapMut, Mmap, MmapError, MmapMut, Protection, capabilities};
+
+use common::perms;
+
+fn map(pages: usize) -> MmapMut {
+    Mmap::builder()
+        .set_read(true)
+        .set_len(pages * capabilities().page_size)
+        .build_mut()
+        .unwrap()
+}
+
+#[test]
+fn protect_changes_single_pages() {
+    let page = capabilities().page_size;
+    let mut map = map(3);
+    map[page] = 7;
+    unsafe { map.protect(page, page, Protection::Read).unwrap() };
+    assert_eq!(perms(map.as_ptr()), "rw-");
+    assert_eq!(perms(map[page..].as_ptr()), "r--");
+    assert_eq!(perms(map[2 * page..].as_ptr()), "rw-");
+    assert_eq!(map[page], 7);
+
+    unsafe { map.protect(page, page, Protection::None).unwrap() };
+    assert_eq!(perms(map[page..].as_ptr()), "---");
+    unsafe { map.protect(page, page, Protection::ReadWrite).unwrap() };
+    map[page] = 8;
+    assert_eq!(map[page], 8);
+}
+
+#[test]
+fn protect_checks_the_range() {
+    let page = capabilities().page_size;
+    let map = map(2);
+    assert!(matches!(
+        unsafe { map.protect(1, page, Protection::Read) },
+        Err(MmapError::Misaligned { .. })
+    ));
+    assert!(matches!(
+        unsafe { map.protect(page, 2 * page, Protection::Read) },
+        Err(MmapError::OutOfRange { .. })
+    ));
+    assert!(matches!(
+        unsafe { map.protect(usize::MAX, 1, Protection::Read) },
+        Err(MmapError::OutOfRange { .. })
+    ));
+    // nothing changed
+    assert_eq!(perms(map.as_ptr()), "rw-");
+    assert!(unsafe { map.protect(page, 0, Protection::Read) }.is_ok());
+}
+
+#[test]
+fn make_read_only_keeps_the_contents() {
+    let mut map = map(2);
+    map.as_mut_slice()[..5].copy_from_slice(b"hello");
+    let map = map.make_read_only().unwrap();
+    assert_eq!(perms(map.as_ptr()), "r--");
+    assert_eq!(perms(map[map.len() - 1..].as_ptr()), "r--");
+    assert_eq!(&map[..5], b"hello");
+}
@@ -0,0 +1,170 @@
+#![cfg(target_os = "linux")]
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use xmmap::{CommonMmapBuilder, LinuxMmapBuilderExt, Mmap, MmapMut, MmapQueue, RawDescriptor};
+
+/// header page plus a ring of one page
+const LEN: usize = 2 * 4096;
+
+fn memfd_map() -> MmapMut {
+    Mmap::builder()
+        .set_len(LEN)
+        .set_read(true)
+        .set_memfd("xmmap-queue-test")
+        .build_mut()
+        .unwrap()
+}
+
+/// another mapping of the same memfd, as another process would have
+fn alias(map: &MmapMut) -> MmapMut {
+    Mmap::builder()
+        .set_len(LEN)
+        .set_read(true)
+        .set_discriptor(RawDescriptor::from(&map.fd().unwrap()))
+        .build_mut()
+        .unwrap()
+}
+
+#[test]
+fn producers_keep_their_order() {
+    const PRODUCERS: u32 = 4;
+    const MESSAGES: u32 = 10_000;
+
+    let map = memfd_map();
+    let producers: Vec<_> = (0..PRODUCERS).map(|_| alias(&map)).collect();
+    let mut queue = MmapQueue::create(map).unwrap();
+    let handles: Vec<_> = producers
+        .into_iter()
+        .enumerate()
+        .map(|(id, map)| {
+            thread::spawn(move || {
+                let queue = MmapQueue::open(map).unwrap();
+                for seq in 0..MESSAGES {
+                    let mut msg = [0; 8];
+                    msg[..4].copy_from_slice(&(id as u32).to_le_bytes());
+                    msg[4..].copy_from_slice(&seq.to_le_bytes());
+                    // vary the length so records pad the end of the ring
+                    queue.send(&msg[..4 + 4 * (seq as usize % 2)]).unwrap();
+                }
+            })
+        })
+        .collect();
+
+    let mut next = [0u32; PRODUCERS as usize];
+    for _ in 0..PRODUCERS * MESSAGES {
+        let msg = queue.recv();
+        let id = u32::from_le_bytes(msg[..4].try_into().unwrap()) as usize;
+        let seq = next[id];
+        assert_eq!(msg.len(), 4 + 4 * (seq as usize % 2));
+        if msg.len() == 8 {
+            assert_eq!(u32::from_le_bytes(msg[4..].try_into().unwrap()), seq);
+        }
+        next[id] += 1;
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(next, [MESSAGES; PRODUCERS as usize]);
+    assert!(queue.try_recv().is_none());
+}
+
+#[test]
+fn records_wrap_around_the_ring() {
+    let mut queue = MmapQueue::create(memfd_map()).unwrap();
+    // 1000 byte messages leave a tail too short for the next record, which
+    // the producer has to pad
+    for round in 0..64u8 {
+        let first = vec![round; 1000];
+        let second = vec![round.wrapping_add(1); 1000];
+        assert!(queue.try_send(&first).unwrap());
+        assert!(queue.try_send(&second).unwrap());
+        assert_eq!(&*queue.try_recv().unwrap(), &first[..]);
+        assert_eq!(&*queue.try_recv().unwrap(), &second[..]);
+    }
+    assert!(queue.try_recv().is_none());
+
+    let max = queue.max_message_len();
+    assert!(queue.try_send(&vec![7; max]).unwrap());
+    assert_eq!(queue.try_recv().unwrap().len(), max);
+    assert!(queue.try_send(&vec![7; max + 1]).is_err());
+}
+
+#[test]
+fn timeouts_expire() {
+    let mut queue = MmapQueue::create(memfd_map()).unwrap();
+    let timeout = Duration::from_millis(50);
+
+    let start = Instant::now();
+    assert!(queue.recv_timeout(timeout).is_none());
+    assert!(start.elapsed() >= timeout);
+
+    let msg = vec![1; 1000];
+    while queue.try_send(&msg).unwrap() {}
+    let start = Instant::now();
+    assert!(!queue.send_timeout(&msg, timeout).unwrap());
+    assert!(start.elapsed() >= timeout);
+
+    // a release makes room again
+    drop(queue.recv_timeout(timeout).unwrap());
+    assert!(queue.send_timeout(&msg, timeout).unwrap());
+}
+
+#[test]
+fn open_rejects_foreign_mappings() {
+    let map = memfd_map();
+    let other = alias(&map);
+    assert!(MmapQueue::open(other).is_err());
+    let _queue = MmapQueue::create(map).unwrap();
+}
+
+#[test]
+fn contended_try_send() {
+    const PRODUCERS: u32 = 8;
+    const MESSAGES: u32 = 20_000;
+
+    let map = memfd_map();
+    let producers: Vec<_> = (0..PRODUCERS).map(|_| alias(&map)).collect();
+    let mut queue = MmapQueue::create(map).unwrap();
+    let handles: Vec<_> = producers
+        .into_iter()
+        .enumerate()
+        .map(|(id, map)| {
+            thread::spawn(move || {
+                let queue = MmapQueue::open(map).unwrap();
+                let mut seq = 0u32;
+                while seq < MESSAGES {
+                    let mut msg = [0; 8];
+                    msg[..4].copy_from_slice(&(id as u32).to_le_bytes());
+                    msg[4..].copy_from_slice(&seq.to_le_bytes());
+                    if queue.try_send(&msg).unwrap() {
+                        seq += 1;
+                    } else {
+                        thread::yield_now();
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let mut next = [0u32; PRODUCERS as usize];
+    let mut received = 0;
+    while received < PRODUCERS * MESSAGES {
+        let Some(msg) = queue.try_recv() else {
+            thread::yield_now();
+            continue;
+        };
+        let id = u32::from_le_bytes(msg[..4].try_into().unwrap()) as usize;
+        assert_eq!(u32::from_le_bytes(msg[4..].try_into().unwrap()), next[id]);
+        next[id] += 1;
+        received += 1;
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(next, [MESSAGES; PRODUCERS as usize]);
+    assert!(queue.try_recv().is_none());
+}
@@ -0,0 +1,96 @@
+use std::slice;
+
+use xmmap::{MmapError, ReservedRegion};
+
+/// a copy of `[offset, offset + len)`, which has to be committed
+fn read(region: &ReservedRegion, offset: usize, len: usize) -> Vec<u8> {
+    unsafe { slice::from_raw_parts(region.as_ptr().add(offset), len) }.to_vec()
+}
+
+/// fill the committed range `[offset, offset + len)` with `byte`
+fn fill(region: &ReservedRegion, offset: usize, len: usize, byte: u8) {
+    unsafe { region.as_mut_ptr().add(offset).write_bytes(byte, len) }
+}
+
+#[test]
+fn reservations_round_up_to_pages() {
+    let region = ReservedRegion::reserve(1).unwrap();
+    assert_eq!(region.len(), region.page_size());
+    assert!(!region.is_empty());
+    assert!(matches!(
+        ReservedRegion::reserve(0),
+        Err(MmapError::InvalidConfig(_))
+    ));
+
+    // address space is cheap, nothing is committed yet
+    let region = ReservedRegion::reserve(1 << 30).unwrap();
+    assert_eq!(region.len(), 1 << 30);
+}
+
+#[test]
+fn committed_pages_are_writable() {
+    let region = ReservedRegion::reserve(1 << 20).unwrap();
+    let page = region.page_size();
+    region.commit(page, 2 * page).unwrap();
+    assert!(read(&region, page, 2 * page).iter().all(|&byte| byte == 0));
+    fill(&region, page, 2 * page, 7);
+
+    // committing again keeps the content
+    region.commit(page, page).unwrap();
+    assert!(read(&region, page, 2 * page).iter().all(|&byte| byte == 7));
+    // the end of the region can be committed too
+    region.commit(region.len() - page, page).unwrap();
+    fill(&region, region.len() - page, page, 1);
+    assert_eq!(read(&region, region.len() - 1, 1), [1]);
+}
+
+#[test]
+fn decommitted_pages_come_back_zeroed() {
+    let region = ReservedRegion::reserve(1 << 20).unwrap();
+    let page = region.page_size();
+    region.commit(0, 2 * page).unwrap();
+    fill(&region, 0, 2 * page, 7);
+
+    region.decommit(0, page).unwrap();
+    region.commit(0, page).unwrap();
+    assert!(read(&region, 0, page).iter().all(|&byte| byte == 0));
+    // the neighbouring page is untouched
+    assert!(read(&region, page, page).iter().all(|&byte| byte == 7));
+
+    // reset pages stay committed
+    region.reset(page, page).unwrap();
+    fill(&region, page, page, 3);
+    assert_eq!(read(&region, page, 1), [3]);
+}
+
+#[test]
+fn ranges_are_checked() {
+    let region = ReservedRegion::reserve(1 << 20).unwrap();
+    let page = region.page_size();
+    assert!(matches!(
+        region.commit(1, page),
+        Err(MmapError::Misaligned { .. })
+    ));
+    assert!(matches!(
+        region.commit(0, page + 1),
+        Err(MmapError::Misaligned { .. })
+    ));
+    assert!(matches!(
+        region.decommit(page / 2, page),
+        Err(MmapError::Misaligned { .. })
+    ));
+    assert!(matches!(
+        region.commit(region.len(), page),
+        Err(MmapError::OutOfRange { .. })
+    ));
+    assert!(matches!(
+        region.reset(page, region.len()),
+        Err(MmapError::OutOfRange { .. })
+    ));
+    assert!(matches!(
+        region.commit(usize::MAX, 1),
+        Err(MmapError::OutOfRange { .. })
+    ));
+    // empty ranges are fine
+    region.commit(region.len(), 0).unwrap();
+}
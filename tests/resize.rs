@@ -0,0 +1,137 @@
+#![cfg(unix)]
+
+use std::fs::{self, File};
+
+#[cfg(target_os = "linux")]
+use xmmap::LinuxMmapBuilderExt;
+use xmmap::{
+    CommonMmapBuilder, CommonMmapMut, Mmap, MmapBuilder, MmapError, MmapMut, UnixMmapBuilderExt,
+};
+
+const GROWN: usize = 100_000;
+const RESERVE: usize = 1 << 20;
+
+/// grow `map` from its few initial bytes, touch the new pages, then shrink
+/// it again
+fn grow_and_shrink(map: &mut MmapMut) {
+    map[0] = 7;
+    let grown = map.resize(GROWN).unwrap();
+    assert_eq!(grown.len(), GROWN);
+    assert_eq!(grown[0], 7);
+    grown[5000] = 1;
+    grown[GROWN - 1] = 2;
+    assert_eq!(map[5000], 1);
+    assert_eq!(map[GROWN - 1], 2);
+
+    map.resize(10).unwrap();
+    assert_eq!(map.len(), 10);
+    assert_eq!(map[0], 7);
+}
+
+fn anonymous() -> MmapBuilder {
+    Mmap::builder().set_read(true).set_len(10)
+}
+
+#[test]
+fn shared_anonymous_needs_a_reservation_to_grow() {
+    let mut map = anonymous().build_mut().unwrap();
+    map[0] = 7;
+    assert!(matches!(map.resize(GROWN), Err(MmapError::Unsupported(_))));
+    // the mapping is left as it was
+    assert_eq!(map.len(), 10);
+    assert_eq!(map[0], 7);
+    map.resize(5).unwrap();
+    assert_eq!(map[..], [7, 0, 0, 0, 0]);
+
+    let mut map = anonymous().set_reserve(RESERVE).build_mut().unwrap();
+    grow_and_shrink(&mut map);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn private_anonymous_grows() {
+    let mut map = anonymous().set_private(true).build_mut().unwrap();
+    grow_and_shrink(&mut map);
+
+    let mut map = anonymous()
+        .set_private(true)
+        .set_reserve(RESERVE)
+        .build_mut()
+        .unwrap();
+    grow_and_shrink(&mut map);
+}
+
+#[test]
+fn reservations_bound_growth() {
+    let mut map = anonymous().set_reserve(RESERVE).build_mut().unwrap();
+    assert!(matches!(
+        map.resize(2 * RESERVE),
+        Err(MmapError::OutOfRange { .. })
+    ));
+    assert_eq!(map.len(), 10);
+
+    // a rejected resize does not grow the file either
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file");
+    fs::write(&path, [0; 10]).unwrap();
+    let file = File::options().read(true).write(true).open(&path).unwrap();
+    let mut map = MmapBuilder::from_file(&file)
+        .unwrap()
+        .set_reserve(RESERVE)
+        .build_mut()
+        .unwrap();
+    assert!(matches!(
+        map.resize(2 * RESERVE),
+        Err(MmapError::OutOfRange { .. })
+    ));
+    assert_eq!(map.len(), 10);
+    assert_eq!(file.metadata().unwrap().len(), 10);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn memfd_grows() {
+    for reserve in [0, RESERVE] {
+        let mut map = anonymous()
+            .set_memfd("xmmap-resize-test")
+            .set_reserve(reserve)
+            .build_mut()
+            .unwrap();
+        grow_and_shrink(&mut map);
+        // the memfd was grown and is never shrunk
+        let memfd = File::from(map.fd().unwrap().try_clone_to_owned().unwrap());
+        assert_eq!(memfd.metadata().unwrap().len(), GROWN as u64);
+    }
+}
+
+#[test]
+fn files_grow() {
+    for reserve in [0, RESERVE] {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, [0; 10]).unwrap();
+        let file = File::options().read(true).write(true).open(&path).unwrap();
+        let mut map = MmapBuilder::from_file(&file)
+            .unwrap()
+            .set_reserve(reserve)
+            .build_mut()
+            .unwrap();
+        map[0] = 7;
+        map.resize(GROWN).unwrap();
+        map[GROWN - 1] = 2;
+        map.flush_all().unwrap();
+        drop(map);
+
+        let contents = fs::read(&path).unwrap();
+        assert_eq!(contents.len(), GROWN);
+        assert_eq!((contents[0], contents[GROWN - 1]), (7, 2));
+
+        let mut map = MmapBuilder::from_file(&file)
+            .unwrap()
+            .set_len(10)
+            .set_reserve(reserve)
+            .build_mut()
+            .unwrap();
+        grow_and_shrink(&mut map);
+    }
+}
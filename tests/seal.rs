@@ -0,0 +1,107 @@
+#![cfg(target_os = "linux")]
+
+use std::{fs::File, io};
+
+use xmmap::{
+    CommonMmapBuilder, LinuxMmapBuilderExt, Mmap, MmapError, MmapMut, RawDescriptor, Seals,
+    UnixMmapBuilderExt,
+};
+
+fn memfd_map() -> MmapMut {
+    Mmap::builder()
+        .set_len(4096)
+        .set_read(true)
+        .set_memfd("xmmap-seal-test")
+        .build_mut()
+        .unwrap()
+}
+
+/// a duplicate of the memfd as a file
+fn memfd(map: &MmapMut) -> File {
+    File::from(map.fd().unwrap().try_clone_to_owned().unwrap())
+}
+
+fn kind(err: &MmapError) -> io::ErrorKind {
+    io::Error::from_raw_os_error(err.raw_os_error().unwrap()).kind()
+}
+
+#[test]
+fn memfd_mappings_have_a_descriptor() {
+    let map = memfd_map();
+    assert!(map.fd().is_some());
+    assert_eq!(memfd(&map).metadata().unwrap().len(), 4096);
+    assert_eq!(map.seals().unwrap(), Seals::empty());
+
+    let anon = Mmap::builder()
+        .set_len(4096)
+        .set_read(true)
+        .build_mut()
+        .unwrap();
+    assert!(matches!(anon.seals(), Err(MmapError::Unsupported(_))));
+    assert!(matches!(
+        anon.add_seals(Seals::GROW),
+        Err(MmapError::Unsupported(_))
+    ));
+}
+
+#[test]
+fn size_seals_stop_truncation() {
+    let map = memfd_map();
+    map.add_seals(Seals::SHRINK | Seals::GROW).unwrap();
+    let seals = map.seals().unwrap();
+    assert!(seals.contains(Seals::SHRINK) && seals.contains(Seals::GROW));
+    assert!(!seals.contains(Seals::WRITE));
+
+    let file = memfd(&map);
+    assert!(file.set_len(8192).is_err());
+    assert!(file.set_len(1024).is_err());
+    assert_eq!(file.metadata().unwrap().len(), 4096);
+}
+
+#[test]
+fn write_seals_wait_for_shared_mappings() {
+    let mut map = memfd_map();
+    map[0] = 1;
+    let err = map.add_seals(Seals::WRITE).unwrap_err();
+    assert_eq!(kind(&err), io::ErrorKind::ResourceBusy);
+
+    // a private mapping does not hold the seal back
+    let read_only = Mmap::builder()
+        .set_len(4096)
+        .set_read(true)
+        .set_discriptor(RawDescriptor::from(&map.fd().unwrap()))
+        .set_private(true)
+        .build()
+        .unwrap();
+    drop(map);
+    read_only.add_seals(Seals::WRITE).unwrap();
+    assert!(read_only.seals().unwrap().contains(Seals::WRITE));
+    assert_eq!(read_only[0], 1);
+}
+
+#[test]
+fn future_write_seals_keep_existing_mappings_writable() {
+    let mut map = memfd_map();
+    map.add_seals(Seals::FUTURE_WRITE).unwrap();
+    map[0] = 7;
+    assert_eq!(map[0], 7);
+
+    // but no new writable mapping
+    assert!(
+        Mmap::builder()
+            .set_len(4096)
+            .set_read(true)
+            .set_discriptor(RawDescriptor::from(&map.fd().unwrap()))
+            .build_mut()
+            .is_err()
+    );
+}
+
+#[test]
+fn the_seal_seal_is_final() {
+    let map = memfd_map();
+    map.add_seals(Seals::SEAL).unwrap();
+    let err = map.add_seals(Seals::GROW).unwrap_err();
+    assert_eq!(kind(&err), io::ErrorKind::PermissionDenied);
+    assert_eq!(map.seals().unwrap(), Seals::SEAL);
+}
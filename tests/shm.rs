@@ -0,0 +1,73 @@
+#![cfg(unix)]
+
+use std::io;
+
+use xmmap::SharedMemory;
+
+/// a name no other test run uses at the same time
+fn name(test: &str) -> String {
+    format!("xmmap-{}-{}", test, std::process::id())
+}
+
+#[test]
+fn opened_regions_alias_the_created_one() {
+    let name = name("alias");
+    let mut created = SharedMemory::create(&name, 4096, 0o600).unwrap();
+    assert_eq!(created.name(), format!("/{}", name));
+    assert_eq!(created.len(), 4096);
+    assert!(created.is_owner());
+
+    let mut opened = SharedMemory::open(&name).unwrap();
+    assert!(!opened.is_owner());
+    assert_eq!(opened.len(), 4096);
+    created[10] = 7;
+    assert_eq!(opened[10], 7);
+    opened[20] = 8;
+    assert_eq!(created[20], 8);
+
+    // the name is taken
+    let err = SharedMemory::create(&name, 4096, 0o600).err().unwrap();
+    assert_eq!(
+        io::Error::from_raw_os_error(err.raw_os_error().unwrap()).kind(),
+        io::ErrorKind::AlreadyExists
+    );
+}
+
+#[test]
+fn open_or_create_opens_existing_regions() {
+    let name = name("open-or-create");
+    let mut created = SharedMemory::open_or_create(&name, 4096, 0o600).unwrap();
+    assert!(created.is_owner());
+    created[0] = 1;
+
+    // `EEXIST` falls back to opening, the length is the existing one
+    let opened = SharedMemory::open_or_create(&name, 8192, 0o600).unwrap();
+    assert!(!opened.is_owner());
+    assert_eq!(opened.len(), 4096);
+    assert_eq!(opened[0], 1);
+}
+
+#[test]
+fn only_the_owner_unlinks() {
+    let name = name("unlink");
+    let created = SharedMemory::create(&name, 4096, 0o600).unwrap();
+
+    // dropping an opened region keeps the name
+    drop(SharedMemory::open(&name).unwrap());
+    let mut opened = SharedMemory::open(&name).unwrap();
+    opened[0] = 3;
+
+    drop(created);
+    assert!(SharedMemory::open(&name).is_err());
+    // existing mappings stay valid
+    assert_eq!(opened[0], 3);
+
+    // ownership can be handed over, or given up
+    let mut created = SharedMemory::create(&name, 4096, 0o600).unwrap();
+    created.set_owner(false);
+    drop(created);
+    let mut opened = SharedMemory::open(&name).unwrap();
+    opened.set_owner(true);
+    drop(opened);
+    assert!(SharedMemory::open(&name).is_err());
+}
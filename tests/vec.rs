@@ -0,0 +1,100 @@
+use std::fs::{self, OpenOptions};
+
+use xmmap::{MmapError, MmapVec};
+
+#[test]
+fn reopen_keeps_elements() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("vec");
+
+    let mut vec = MmapVec::<u32>::open(&path).unwrap();
+    vec.extend_from_slice(&[1, 2, 3]).unwrap();
+    // grow past the first remap
+    for i in 0..10_000 {
+        vec.push(i).unwrap();
+    }
+    assert_eq!(vec.pop(), Some(9_999));
+    vec.sync().unwrap();
+    drop(vec);
+
+    let mut vec = MmapVec::<u32>::open(&path).unwrap();
+    assert_eq!(vec.len(), 10_002);
+    assert_eq!(&vec[..4], &[1, 2, 3, 0]);
+    assert_eq!(vec.last(), Some(&9_998));
+    vec.truncate(2);
+    vec.shrink_to_fit().unwrap();
+    vec.sync().unwrap();
+    drop(vec);
+
+    let vec = MmapVec::<u32>::open(&path).unwrap();
+    assert_eq!(&vec[..], &[1, 2]);
+}
+
+#[test]
+fn reopen_with_another_element_size_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("vec");
+
+    let mut vec = MmapVec::<u32>::open(&path).unwrap();
+    vec.push(1).unwrap();
+    vec.sync().unwrap();
+    drop(vec);
+
+    assert!(matches!(
+        MmapVec::<u64>::open(&path),
+        Err(MmapError::InvalidConfig(_))
+    ));
+    // the file is left alone
+    assert_eq!(&MmapVec::<u32>::open(&path).unwrap()[..], &[1]);
+}
+
+#[test]
+fn reopen_with_a_length_past_the_file_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("vec");
+
+    let mut vec = MmapVec::<u64>::open(&path).unwrap();
+    for i in 0..1_000 {
+        vec.push(i).unwrap();
+    }
+    vec.sync().unwrap();
+    drop(vec);
+
+    // cut the file below the stored length
+    let file = OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(64 + 8 * 10).unwrap();
+    drop(file);
+    assert!(matches!(
+        MmapVec::<u64>::open(&path),
+        Err(MmapError::OutOfRange {
+            end: 1_000,
+            len: 10,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn open_rejects_other_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("vec");
+    fs::write(&path, vec![0xab; 4096]).unwrap();
+    assert!(matches!(
+        MmapVec::<u8>::open(&path),
+        Err(MmapError::InvalidConfig(_))
+    ));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn growth_allocates_the_file() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("vec");
+    let mut vec = MmapVec::<u64>::open(&path).unwrap();
+    vec.reserve(100_000).unwrap();
+    let metadata = fs::metadata(&path).unwrap();
+    assert!(metadata.len() >= 800_000);
+    assert!(metadata.blocks() * 512 >= metadata.len());
+}
@@ -0,0 +1,85 @@
+use std::{num::NonZeroUsize, thread};
+
+use xmmap::{CommonMmapBuilder, Mmap, MmapError};
+
+#[test]
+fn failed_splits_keep_the_view() {
+    let map = Mmap::builder()
+        .set_read(true)
+        .set_len(100)
+        .build_mut()
+        .unwrap();
+    let mut view = map.into_view_mut();
+
+    assert!(matches!(
+        view.split_off(101),
+        Err(MmapError::OutOfRange { .. })
+    ));
+    assert_eq!(view.len(), 100);
+    view.fill(1);
+
+    let mut tail = view.split_off(60).unwrap();
+    assert_eq!((view.len(), tail.offset(), tail.len()), (60, 60, 40));
+    tail.fill(2);
+    let view = view.into_view();
+    assert!(view.iter().all(|&byte| byte == 1));
+    assert!(tail.iter().all(|&byte| byte == 2));
+}
+
+#[test]
+fn writable_views_split_at() {
+    let map = Mmap::builder()
+        .set_read(true)
+        .set_len(100)
+        .build_mut()
+        .unwrap();
+    let view = map.into_view_mut();
+
+    let Err((view, err)) = view.split_at(101) else {
+        panic!("split past the end");
+    };
+    assert!(matches!(err, MmapError::OutOfRange { .. }));
+    assert_eq!(view.len(), 100);
+
+    let Ok((mut head, mut tail)) = view.split_at(30) else {
+        panic!("split inside the view");
+    };
+    assert_eq!((head.len(), tail.offset(), tail.len()), (30, 30, 70));
+    head.fill(1);
+    tail.fill(2);
+    let (head, tail) = (head.into_view(), tail.into_view());
+    assert!(head.iter().all(|&byte| byte == 1));
+    assert!(tail.iter().all(|&byte| byte == 2));
+}
+
+#[test]
+fn chunks_are_written_from_threads() {
+    let map = Mmap::builder()
+        .set_read(true)
+        .set_len(100)
+        .build_mut()
+        .unwrap();
+    let chunk_len = NonZeroUsize::new(30).unwrap();
+    let handles: Vec<_> = map
+        .into_view_mut()
+        .chunks(chunk_len)
+        .enumerate()
+        .map(|(i, mut chunk)| {
+            thread::spawn(move || {
+                chunk.fill(i as u8);
+                chunk.into_view()
+            })
+        })
+        .collect();
+    let chunks: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(
+        chunks.iter().map(|c| c.len()).collect::<Vec<_>>(),
+        [30, 30, 30, 10]
+    );
+
+    let first = chunks[0].slice(0..30).unwrap();
+    assert_eq!(first[..], [0; 30]);
+    let bytes: Vec<u8> = chunks.iter().flat_map(|c| c.iter().copied()).collect();
+    assert_eq!(bytes[95], 3);
+    assert_eq!(chunks[1].chunks(NonZeroUsize::new(7).unwrap()).count(), 5);
+}